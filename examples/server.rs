@@ -32,6 +32,7 @@ async fn main() -> anyhow::Result<()> {
     request_timeout_sec: 10,
     max_retry: 3,
     protocol: Protocol::H1,
+    ..Default::default()
   };
 
   let upstream_name = FastStr::from("test_upstream");
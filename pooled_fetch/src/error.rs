@@ -11,3 +11,14 @@ pub enum Error {
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+  /// 连接失败, 请求还没有发出, 可以安全地重试
+  pub fn is_connect(&self) -> bool {
+    match self {
+      Error::Io(_) => true,
+      Error::Hyper(err) => err.is_canceled(),
+      Error::AddrParse(_) => false,
+    }
+  }
+}
@@ -0,0 +1,100 @@
+use faststr::FastStr;
+use http::{HeaderMap, Method, Request, Response, StatusCode, header::AUTHORIZATION};
+use http_body_util::{BodyExt, Full, combinators::BoxBody};
+use hyper::body::Bytes;
+
+use crate::{Error, Purge, Result, Route, normalize_host};
+
+type Res = Response<BoxBody<Bytes, Error>>;
+
+fn response(status: StatusCode, body: impl Into<Bytes>) -> Result<Res> {
+  Ok(
+    Response::builder().status(status).body(
+      Full::new(body.into())
+        .map_err(|never| match never {})
+        .boxed(),
+    )?,
+  )
+}
+
+// 去掉 url 的 scheme, 和缓存键的格式一致
+fn strip_scheme(url: &str) -> &str {
+  url
+    .strip_prefix("https://")
+    .or_else(|| url.strip_prefix("http://"))
+    .unwrap_or(url)
+}
+
+/// 管理接口, 通过独立的域名访问, 需要请求头 Authorization: Bearer <token>
+///
+/// POST /purge?url=example.com/a&host=example.com&prefix=example.com/docs/&tag=t1
+/// 参数可以重复, 返回清除的缓存份数
+#[derive(Debug, Clone)]
+pub struct Admin {
+  pub host: FastStr,
+  token: FastStr,
+}
+
+impl Admin {
+  pub fn new(host: impl Into<FastStr>, token: impl Into<FastStr>) -> Self {
+    Self {
+      host: normalize_host(&host.into()),
+      token: token.into(),
+    }
+  }
+
+  // 比较耗时与 token 内容无关
+  fn is_auth(&self, headers: &HeaderMap) -> bool {
+    let Some(token) = headers
+      .get(AUTHORIZATION)
+      .and_then(|v| v.to_str().ok())
+      .and_then(|v| v.strip_prefix("Bearer "))
+    else {
+      return false;
+    };
+    !self.token.is_empty()
+      && token.len() == self.token.len()
+      && token
+        .bytes()
+        .zip(self.token.bytes())
+        .fold(0, |acc, (a, b)| acc | (a ^ b))
+        == 0
+  }
+
+  pub fn serve<B>(&self, req: &Request<B>, route: &Route) -> Result<Res> {
+    if !self.is_auth(req.headers()) {
+      return response(StatusCode::UNAUTHORIZED, &b"401: Unauthorized"[..]);
+    }
+    if req.uri().path() != "/purge" {
+      return response(StatusCode::NOT_FOUND, &b"404: Not Found"[..]);
+    }
+    if req.method() != Method::POST {
+      return response(StatusCode::METHOD_NOT_ALLOWED, &b""[..]);
+    }
+    let mut purge_li = Vec::new();
+    for (k, v) in form_urlencoded::parse(req.uri().query().unwrap_or("").as_bytes()) {
+      let purge = match &*k {
+        "url" => Purge::Url(strip_scheme(&v).to_owned().into()),
+        "host" => Purge::Host(normalize_host(&v)),
+        "prefix" => Purge::Prefix(strip_scheme(&v).to_owned().into()),
+        "tag" => Purge::Tag(v.into_owned().into()),
+        _ => return response(StatusCode::BAD_REQUEST, format!("unknown param: {k}")),
+      };
+      purge_li.push(purge);
+    }
+    if purge_li.is_empty() {
+      return response(
+        StatusCode::BAD_REQUEST,
+        &b"url, host, prefix or tag required"[..],
+      );
+    }
+    let n: usize = purge_li
+      .into_iter()
+      .map(|purge| {
+        log::info!("purge {purge:?}");
+        route.cache.purge(purge)
+      })
+      .sum();
+    response(StatusCode::OK, n.to_string())
+  }
+}
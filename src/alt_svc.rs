@@ -0,0 +1,70 @@
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering::Relaxed};
+
+use http::{HeaderMap, HeaderValue, Version, header::ALT_SVC};
+
+/// 通告 h3 端口的 Alt-Svc, 运行时可调整
+#[derive(Debug)]
+pub struct AltSvc {
+  // h3 实际监听的端口, 0 表示未启用 h3
+  port: AtomicU16,
+  // 浏览器缓存的秒数
+  ma: AtomicU64,
+  // 为 false 时发送 clear, 让浏览器撤销 h3, 用于维护
+  on: AtomicBool,
+  // 是否也在 h1 响应上通告
+  h1: AtomicBool,
+}
+
+impl Default for AltSvc {
+  fn default() -> Self {
+    Self {
+      port: AtomicU16::new(0),
+      ma: AtomicU64::new(86400),
+      on: AtomicBool::new(true),
+      h1: AtomicBool::new(false),
+    }
+  }
+}
+
+impl AltSvc {
+  pub fn set_port(&self, port: u16) {
+    self.port.store(port, Relaxed);
+  }
+
+  pub fn set_ma(&self, ma: u64) {
+    self.ma.store(ma, Relaxed);
+  }
+
+  pub fn set_on(&self, on: bool) {
+    self.on.store(on, Relaxed);
+  }
+
+  pub fn set_h1(&self, h1: bool) {
+    self.h1.store(h1, Relaxed);
+  }
+
+  /// 响应的 Alt-Svc, site_h3 为站点是否启用 h3, 不需要发送时返回 None
+  pub fn value(&self, version: Version, site_h3: bool) -> Option<HeaderValue> {
+    let send = match version {
+      Version::HTTP_2 => true,
+      Version::HTTP_10 | Version::HTTP_11 => self.h1.load(Relaxed),
+      _ => false,
+    };
+    let port = self.port.load(Relaxed);
+    if !send || port == 0 {
+      return None;
+    }
+    if !(site_h3 && self.on.load(Relaxed)) {
+      return Some(HeaderValue::from_static("clear"));
+    }
+    let ma = self.ma.load(Relaxed);
+    HeaderValue::from_str(&format!("h3=\":{port}\"; ma={ma}")).ok()
+  }
+
+  /// 设置响应的 Alt-Svc, 覆盖上游返回的值
+  pub fn apply(&self, headers: &mut HeaderMap, version: Version, site_h3: bool) {
+    if let Some(val) = self.value(version, site_h3) {
+      headers.insert(ALT_SVC, val);
+    }
+  }
+}
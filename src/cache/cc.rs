@@ -0,0 +1,47 @@
+use http::{HeaderMap, header::CACHE_CONTROL};
+
+/// 解析后的 Cache-Control
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheControl {
+  pub no_store: bool,
+  pub no_cache: bool,
+  pub private: bool,
+  pub public: bool,
+  // 过期后必须向上游验证, 不能用旧内容
+  pub must_revalidate: bool,
+  pub proxy_revalidate: bool,
+  pub max_age: Option<u64>,
+  pub s_maxage: Option<u64>,
+  pub stale_while_revalidate: Option<u64>,
+  pub stale_if_error: Option<u64>,
+}
+
+impl CacheControl {
+  pub fn parse(headers: &HeaderMap) -> Self {
+    let mut cc = Self::default();
+    for item in headers
+      .get_all(CACHE_CONTROL)
+      .iter()
+      .filter_map(|v| v.to_str().ok())
+      .flat_map(|v| v.split(','))
+    {
+      let (k, v) = item.split_once('=').unwrap_or((item, ""));
+      let k = k.trim().to_ascii_lowercase();
+      let sec = || v.trim().trim_matches('"').parse().ok();
+      match k.as_str() {
+        "no-store" => cc.no_store = true,
+        "no-cache" => cc.no_cache = true,
+        "private" => cc.private = true,
+        "public" => cc.public = true,
+        "must-revalidate" => cc.must_revalidate = true,
+        "proxy-revalidate" => cc.proxy_revalidate = true,
+        "max-age" => cc.max_age = sec(),
+        "s-maxage" => cc.s_maxage = sec(),
+        "stale-while-revalidate" => cc.stale_while_revalidate = sec(),
+        "stale-if-error" => cc.stale_if_error = sec(),
+        _ => {}
+      }
+    }
+    cc
+  }
+}
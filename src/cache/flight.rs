@@ -0,0 +1,180 @@
+use std::{sync::Arc, time::Duration};
+
+use futures_util::stream;
+use http::{HeaderMap, Response, StatusCode};
+use http_body::Frame;
+use http_body_util::{BodyExt, StreamBody, combinators::BoxBody};
+use hyper::body::Bytes;
+use parking_lot::Mutex;
+use tokio::sync::watch;
+
+use crate::Error;
+
+type Body = BoxBody<Bytes, Error>;
+type Res = Response<Body>;
+
+#[derive(Debug, Default)]
+struct State {
+  head: Option<(StatusCode, HeaderMap)>,
+  chunk_li: Vec<Bytes>,
+  byte: u64,
+  end: bool,
+  fail: bool,
+  // 上游响应体的错误, 交给领头请求
+  err: Option<Error>,
+  // 缓冲超过上限, 不再缓冲, 剩下的上游响应体交给领头请求直接读
+  rest: Option<Body>,
+  over: bool,
+}
+
+// 读取共享响应体的位置, 领头请求在缓冲超限后改为直接读上游
+enum Read {
+  Buf(Arc<Flight>, usize, watch::Receiver<u64>),
+  Rest(Body),
+  End,
+}
+
+/// 进行中的上游请求, 相同的并发请求共享它的响应
+#[derive(Debug)]
+pub struct Flight {
+  state: Mutex<State>,
+  // 状态每次变化时加一, 唤醒等待的请求
+  tx: watch::Sender<u64>,
+}
+
+impl Default for Flight {
+  fn default() -> Self {
+    Self {
+      state: Mutex::new(State::default()),
+      tx: watch::Sender::new(0),
+    }
+  }
+}
+
+impl Flight {
+  fn notify(&self) {
+    self.tx.send_modify(|n| *n += 1);
+  }
+
+  pub fn fail(&self) {
+    self.state.lock().fail = true;
+    self.notify();
+  }
+
+  /// 已读到的响应体不超过 max_byte 时才能加入, 避免为后来者缓冲大文件
+  pub fn joinable(&self, max_byte: u64) -> bool {
+    let state = self.state.lock();
+    !state.fail && !state.over && state.byte <= max_byte
+  }
+
+  /// 等待领头请求的响应头, 超时或失败时返回 None
+  pub async fn follow(self: Arc<Self>, timeout: Duration) -> Option<Res> {
+    let mut rx = self.tx.subscribe();
+    let wait = async {
+      loop {
+        {
+          let state = self.state.lock();
+          if state.fail {
+            return None;
+          }
+          if let Some(head) = &state.head {
+            return Some(head.clone());
+          }
+        }
+        rx.changed().await.ok()?;
+      }
+    };
+    let (status, headers) = tokio::time::timeout(timeout, wait).await.ok()??;
+    let mut res = Response::new(self.body(false));
+    *res.status_mut() = status;
+    *res.headers_mut() = headers;
+    Some(res)
+  }
+
+  // 从头读取共享的响应体
+  // 上游出错或缓冲超限时, 读不到完整响应体的请求以错误结束, 不能让客户端当作完整响应
+  fn body(self: Arc<Self>, lead: bool) -> Body {
+    let rx = self.tx.subscribe();
+    let stream = stream::unfold(Read::Buf(self, 0, rx), move |read| async move {
+      let (flight, pos, mut rx) = match read {
+        Read::Buf(flight, pos, rx) => (flight, pos, rx),
+        Read::Rest(mut body) => {
+          return body.frame().await.map(|frame| (frame, Read::Rest(body)));
+        }
+        Read::End => return None,
+      };
+      loop {
+        let rest = {
+          let mut state = flight.state.lock();
+          if let Some(chunk) = state.chunk_li.get(pos) {
+            let frame = Ok(Frame::data(chunk.clone()));
+            drop(state);
+            return Some((frame, Read::Buf(flight, pos + 1, rx)));
+          }
+          if state.end {
+            return None;
+          }
+          if state.fail || (state.over && !lead) {
+            let err = lead.then(|| state.err.take()).flatten();
+            return Some((Err(err.unwrap_or(Error::FlightAbort)), Read::End));
+          }
+          state.rest.take()
+        };
+        if let Some(mut body) = rest {
+          return body.frame().await.map(|frame| (frame, Read::Rest(body)));
+        }
+        rx.changed().await.ok()?;
+      }
+    });
+    StreamBody::new(stream).boxed()
+  }
+
+  /// 领头请求拿到响应后调用: 后台读上游响应体, 自己和跟随者都从共享缓冲读
+  /// 缓冲超过 max_byte 时停止, 领头请求接着直接读上游, 跟随者以错误结束
+  /// 读完, 出错或超限后调用 done
+  pub fn lead(
+    self: Arc<Self>,
+    res: Res,
+    max_byte: u64,
+    done: impl FnOnce() + Send + 'static,
+  ) -> Res {
+    let (parts, mut body) = res.into_parts();
+    self.state.lock().head = Some((parts.status, parts.headers.clone()));
+    self.notify();
+    let flight = self.clone();
+    tokio::spawn(async move {
+      loop {
+        match body.frame().await {
+          Some(Ok(frame)) => {
+            if let Ok(data) = frame.into_data() {
+              let mut state = flight.state.lock();
+              state.byte += data.len() as u64;
+              state.chunk_li.push(data);
+              if state.byte > max_byte {
+                log::warn!("flight: body over {max_byte} bytes, stop buffering");
+                state.over = true;
+                state.rest = Some(body);
+                break;
+              }
+            }
+          }
+          Some(Err(err)) => {
+            log::warn!("flight: {err}");
+            let mut state = flight.state.lock();
+            state.fail = true;
+            state.err = Some(err);
+            break;
+          }
+          None => {
+            flight.state.lock().end = true;
+            break;
+          }
+        }
+        flight.notify();
+      }
+      flight.notify();
+      done();
+    });
+    Response::from_parts(parts, self.body(true))
+  }
+}
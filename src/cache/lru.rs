@@ -0,0 +1,138 @@
+use std::{
+  collections::{BTreeMap, HashMap},
+  sync::Arc,
+};
+
+use faststr::FastStr;
+use http::HeaderName;
+
+use super::Entry;
+
+// 同一个地址按 Vary 可能有多份
+#[derive(Debug, Default)]
+struct Slot {
+  entry_li: Vec<Arc<Entry>>,
+  tick: u64,
+  byte: u64,
+}
+
+/// 按字节数限制大小的 LRU
+#[derive(Debug, Default)]
+pub struct Lru {
+  map: HashMap<FastStr, Slot>,
+  // 最近使用的序号 -> 键, 最小的最久未用
+  order: BTreeMap<u64, FastStr>,
+  tick: u64,
+  pub byte: u64,
+}
+
+impl Lru {
+  fn touch(&mut self, key: &str) -> Option<&mut Slot> {
+    let slot = self.map.get_mut(key)?;
+    self.tick += 1;
+    if let Some(key) = self.order.remove(&slot.tick) {
+      self.order.insert(self.tick, key);
+    }
+    slot.tick = self.tick;
+    Some(slot)
+  }
+
+  pub fn get(&mut self, key: &str) -> Option<&[Arc<Entry>]> {
+    self.touch(key).map(|slot| &slot.entry_li[..])
+  }
+
+  /// 存入后淘汰最久未用的, 直到不超过 max_byte
+  pub fn put(&mut self, key: FastStr, entry: Arc<Entry>, max_byte: u64) {
+    let byte = entry.byte();
+    if byte > max_byte {
+      return;
+    }
+    if !self.map.contains_key(&key) {
+      self.tick += 1;
+      self.order.insert(self.tick, key.clone());
+      self.map.insert(
+        key.clone(),
+        Slot {
+          tick: self.tick,
+          ..Default::default()
+        },
+      );
+    }
+    if let Some(slot) = self.touch(&key) {
+      let mut freed = 0;
+      slot.entry_li.retain(|e| {
+        let same = e.vary == entry.vary;
+        if same {
+          freed += e.byte();
+        }
+        !same
+      });
+      slot.entry_li.push(entry);
+      slot.byte = slot.byte + byte - freed;
+      self.byte = self.byte + byte - freed;
+    }
+    while self.byte > max_byte {
+      let Some((_, key)) = self.order.pop_first() else {
+        break;
+      };
+      if let Some(slot) = self.map.remove(&key) {
+        self.byte -= slot.byte;
+      }
+    }
+  }
+
+  pub fn remove(&mut self, key: &str) -> bool {
+    match self.map.remove(key) {
+      Some(slot) => {
+        self.order.remove(&slot.tick);
+        self.byte -= slot.byte;
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// 删除 f 返回 false 的响应, 返回删除的份数
+  pub fn retain(&mut self, mut f: impl FnMut(&str, &Entry) -> bool) -> usize {
+    let mut n = 0;
+    let mut freed = 0;
+    let mut empty_li = Vec::new();
+    for (key, slot) in self.map.iter_mut() {
+      slot.entry_li.retain(|e| {
+        let keep = f(key, e);
+        if !keep {
+          n += 1;
+          slot.byte -= e.byte();
+          freed += e.byte();
+        }
+        keep
+      });
+      if slot.entry_li.is_empty() {
+        empty_li.push((slot.tick, key.clone()));
+      }
+    }
+    for (tick, key) in empty_li {
+      self.order.remove(&tick);
+      self.map.remove(&key);
+    }
+    self.byte -= freed;
+    n
+  }
+
+  /// 已缓存的各份响应 Vary 涉及的请求头, 不影响淘汰顺序
+  pub fn vary_of(&self, key: &str) -> Vec<HeaderName> {
+    let mut li: Vec<HeaderName> = Vec::new();
+    if let Some(slot) = self.map.get(key) {
+      for (name, _) in slot.entry_li.iter().flat_map(|e| e.vary.iter()) {
+        if !li.contains(name) {
+          li.push(name.clone());
+        }
+      }
+    }
+    li
+  }
+
+  pub fn len(&self) -> usize {
+    self.map.len()
+  }
+}
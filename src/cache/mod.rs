@@ -0,0 +1,681 @@
+mod cc;
+mod flight;
+mod lru;
+mod tee;
+
+use std::{
+  sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering::Relaxed},
+  },
+  time::{Duration, UNIX_EPOCH},
+};
+
+pub use cc::CacheControl;
+use coarsetime::Clock;
+use dashmap::{DashMap, mapref::entry::Entry as MapEntry};
+use faststr::FastStr;
+use flight::Flight;
+use http::{
+  HeaderMap, HeaderName, HeaderValue, Method, Response, StatusCode,
+  header::{
+    ACCEPT_ENCODING, ACCEPT_LANGUAGE, AGE, AUTHORIZATION, CONTENT_LENGTH, COOKIE, DATE, ETAG,
+    EXPIRES, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, SET_COOKIE, VARY,
+  },
+  request::Parts,
+};
+use http_body_util::{BodyExt, Empty, Full, combinators::BoxBody};
+use hyper::body::Bytes;
+use lru::Lru;
+use parking_lot::Mutex;
+use tee::Tee;
+
+use crate::{Error, Result};
+
+type Res = Response<BoxBody<Bytes, Error>>;
+
+const X_CACHE: HeaderName = HeaderName::from_static("x-cache");
+
+// 清除记录的保留时长, 毫秒, 期间完成的旧请求不会再存入缓存
+const BAN_KEEP_MS: u64 = 600_000;
+
+fn now_ms() -> u64 {
+  Clock::now_since_epoch().as_millis()
+}
+
+fn http_date(v: Option<&HeaderValue>) -> Option<u64> {
+  let t = httpdate::parse_http_date(v?.to_str().ok()?).ok()?;
+  Some(t.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
+
+/// 站点的缓存策略
+#[derive(Debug, Clone)]
+pub struct CacheRule {
+  // 覆盖上游给出的新鲜期, 秒
+  pub ttl: Option<u64>,
+  // 上游没有给出新鲜期时使用, 秒, 0 表示每次都要向上游验证
+  pub default_ttl: u64,
+  // 响应体超过该字节数不缓存
+  pub max_body: u64,
+  // 上游没有给出 stale-while-revalidate 时使用, 秒, 期间先返回旧内容并在后台刷新
+  pub stale_revalidate: u64,
+  // 上游没有给出 stale-if-error 时使用, 秒, 期间上游出错时返回旧内容
+  pub stale_error: u64,
+  // 过期后最多还能用多久, 秒, 限制上面两项
+  pub max_stale: u64,
+  // 相同的并发 GET 请求合并为一次上游请求, 其余请求最多等待的秒数, 0 表示不合并
+  pub flight_timeout_sec: u64,
+  // 从该响应头读取空格分隔的标签, 用于按标签清除, 不转发给客户端
+  pub tag_header: Option<HeaderName>,
+}
+
+impl Default for CacheRule {
+  fn default() -> Self {
+    Self {
+      ttl: None,
+      default_ttl: 0,
+      max_body: 8 << 20,
+      stale_revalidate: 0,
+      stale_error: 0,
+      max_stale: 86400,
+      flight_timeout_sec: 10,
+      tag_header: Some(HeaderName::from_static("surrogate-key")),
+    }
+  }
+}
+
+impl CacheRule {
+  /// 响应可缓存时返回新鲜期 (毫秒)
+  pub fn ttl_of(
+    &self,
+    status: StatusCode,
+    headers: &HeaderMap,
+    req_headers: &HeaderMap,
+  ) -> Option<u64> {
+    if !matches!(
+      status.as_u16(),
+      200 | 203 | 204 | 300 | 301 | 308 | 404 | 405 | 410 | 414 | 501
+    ) || headers.contains_key(SET_COOKIE)
+    {
+      return None;
+    }
+    let cc = CacheControl::parse(headers);
+    if cc.no_store || cc.private {
+      return None;
+    }
+    // 带认证的请求默认不能给别人用
+    if req_headers.contains_key(AUTHORIZATION) && !(cc.public || cc.s_maxage.is_some()) {
+      return None;
+    }
+    let sec = if let Some(ttl) = self.ttl {
+      ttl
+    } else if cc.no_cache {
+      0
+    } else if let Some(sec) = cc.s_maxage.or(cc.max_age) {
+      sec
+    } else if let Some(expires) = http_date(headers.get(EXPIRES)) {
+      let date = http_date(headers.get(DATE)).unwrap_or(now_ms() / 1000);
+      expires.saturating_sub(date)
+    } else {
+      self.default_ttl
+    };
+    let age = headers
+      .get(AGE)
+      .and_then(|v| v.to_str().ok())
+      .and_then(|v| v.parse::<u64>().ok())
+      .unwrap_or(0);
+    let ttl = sec.saturating_sub(age) * 1000;
+    // 已过期又无法验证的不用存
+    (ttl > 0 || headers.contains_key(ETAG) || headers.contains_key(LAST_MODIFIED)).then_some(ttl)
+  }
+
+  /// 过期后可用旧内容的时长 (毫秒): (后台刷新, 上游出错)
+  pub fn stale_of(&self, headers: &HeaderMap) -> (u64, u64) {
+    let cc = CacheControl::parse(headers);
+    if cc.no_cache || cc.must_revalidate || cc.proxy_revalidate {
+      return (0, 0);
+    }
+    let cap = |sec: u64| sec.min(self.max_stale) * 1000;
+    (
+      cap(cc.stale_while_revalidate.unwrap_or(self.stale_revalidate)),
+      cap(cc.stale_if_error.unwrap_or(self.stale_error)),
+    )
+  }
+}
+
+// 响应 Vary 中的请求头, 有 * 时返回 None
+fn vary_li(headers: &HeaderMap) -> Option<Vec<HeaderName>> {
+  let mut li = Vec::new();
+  for name in headers
+    .get_all(VARY)
+    .iter()
+    .filter_map(|v| v.to_str().ok())
+    .flat_map(|v| v.split(','))
+  {
+    let name = name.trim();
+    if name == "*" {
+      return None;
+    }
+    if let Ok(name) = HeaderName::try_from(name) {
+      li.push(name);
+    }
+  }
+  Some(li)
+}
+
+/// 缓存的响应
+#[derive(Debug)]
+pub struct Entry {
+  pub status: StatusCode,
+  pub headers: HeaderMap,
+  pub body: Bytes,
+  // 存入时间, 毫秒
+  pub time: u64,
+  // 新鲜期, 毫秒
+  pub ttl: u64,
+  // 过期后可先返回旧内容并后台刷新的时长, 毫秒
+  pub stale_revalidate: u64,
+  // 过期后上游出错时可返回旧内容的时长, 毫秒
+  pub stale_error: u64,
+  // Vary 涉及的请求头及存入时请求中的值
+  pub vary: Box<[(HeaderName, Option<HeaderValue>)]>,
+  // 响应的标签, 来自 CacheRule::tag_header
+  pub tag_li: Box<[FastStr]>,
+}
+
+impl Entry {
+  pub fn byte(&self) -> u64 {
+    let header: usize = self
+      .headers
+      .iter()
+      .map(|(k, v)| k.as_str().len() + v.len())
+      .sum();
+    (self.body.len() + header + 256) as u64
+  }
+
+  pub fn is_fresh(&self, now: u64) -> bool {
+    now < self.time + self.ttl
+  }
+
+  fn can_revalidate_stale(&self, now: u64) -> bool {
+    now < self.time + self.ttl + self.stale_revalidate
+  }
+
+  fn can_error_stale(&self, now: u64) -> bool {
+    now < self.time + self.ttl + self.stale_error
+  }
+
+  fn is_match(&self, req_headers: &HeaderMap) -> bool {
+    self
+      .vary
+      .iter()
+      .all(|(name, v)| req_headers.get(name) == v.as_ref())
+  }
+
+  fn has_validator(&self) -> bool {
+    self.headers.contains_key(ETAG) || self.headers.contains_key(LAST_MODIFIED)
+  }
+
+  // 客户端的条件请求是否命中
+  fn not_modified(&self, req_headers: &HeaderMap) -> bool {
+    if let Some(inm) = req_headers.get(IF_NONE_MATCH).and_then(|v| v.to_str().ok()) {
+      let Some(etag) = self.headers.get(ETAG).and_then(|v| v.to_str().ok()) else {
+        return false;
+      };
+      let etag = etag.trim_start_matches("W/");
+      return inm
+        .split(',')
+        .map(|t| t.trim())
+        .any(|t| t == "*" || t.trim_start_matches("W/") == etag);
+    }
+    match (
+      http_date(req_headers.get(IF_MODIFIED_SINCE)),
+      http_date(self.headers.get(LAST_MODIFIED)),
+    ) {
+      (Some(since), Some(last)) => last <= since,
+      _ => false,
+    }
+  }
+
+  /// 用缓存生成响应, 附上 Age
+  pub fn response(&self, req_headers: &HeaderMap, head: bool) -> Res {
+    let mut headers = self.headers.clone();
+    let age = now_ms().saturating_sub(self.time) / 1000;
+    headers.insert(AGE, HeaderValue::from(age));
+    let (status, body) = if self.not_modified(req_headers) {
+      headers.remove(CONTENT_LENGTH);
+      (StatusCode::NOT_MODIFIED, None)
+    } else {
+      (self.status, (!head).then(|| self.body.clone()))
+    };
+    let body = match body {
+      Some(body) => Full::new(body).map_err(|never| match never {}).boxed(),
+      None => Empty::new().map_err(|never| match never {}).boxed(),
+    };
+    let mut res = Response::new(body);
+    *res.status_mut() = status;
+    *res.headers_mut() = headers;
+    res
+  }
+
+  // 上游返回 304 后, 用新的响应头更新缓存
+  fn refresh(&self, headers: &HeaderMap, rule: &CacheRule, req_headers: &HeaderMap) -> Self {
+    let mut merged = self.headers.clone();
+    for name in headers.keys() {
+      if name != CONTENT_LENGTH {
+        merged.remove(name);
+        for v in headers.get_all(name) {
+          merged.append(name, v.clone());
+        }
+      }
+    }
+    let ttl = rule.ttl_of(self.status, &merged, req_headers).unwrap_or(0);
+    let (stale_revalidate, stale_error) = rule.stale_of(&merged);
+    Self {
+      status: self.status,
+      headers: merged,
+      body: self.body.clone(),
+      time: now_ms(),
+      ttl,
+      stale_revalidate,
+      stale_error,
+      vary: self.vary.clone(),
+      tag_li: self.tag_li.clone(),
+    }
+  }
+}
+
+/// 清除缓存的条件, 地址为转发给上游的域名加路径, 如 example.com/a?b=1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Purge {
+  /// 单个地址
+  Url(FastStr),
+  /// 域名下的所有地址
+  Host(FastStr),
+  /// 以此开头的地址, 如 example.com/docs/
+  Prefix(FastStr),
+  /// 带有该标签的响应
+  Tag(FastStr),
+}
+
+impl Purge {
+  pub fn is_match(&self, key: &str, entry: &Entry) -> bool {
+    match self {
+      Purge::Url(url) => key == url,
+      Purge::Host(host) => key
+        .strip_prefix(host.as_str())
+        .is_some_and(|path| path.starts_with('/')),
+      Purge::Prefix(prefix) => key.starts_with(prefix.as_str()),
+      Purge::Tag(tag) => entry.tag_li.contains(tag),
+    }
+  }
+}
+
+// 响应头中空格分隔的标签
+fn tag_li(headers: &HeaderMap, name: &HeaderName) -> Box<[FastStr]> {
+  headers
+    .get_all(name)
+    .iter()
+    .filter_map(|v| v.to_str().ok())
+    .flat_map(|v| v.split_ascii_whitespace())
+    .map(FastStr::new)
+    .collect()
+}
+
+// 标记返回的是过期的旧内容
+fn stale(mut res: Res) -> Res {
+  res
+    .headers_mut()
+    .insert(X_CACHE, HeaderValue::from_static("stale"));
+  res
+}
+
+/// 内存中的响应缓存, 所有站点共用, 按字节数 LRU 淘汰
+#[derive(Debug)]
+pub struct Cache {
+  lru: Mutex<Lru>,
+  max_byte: AtomicU64,
+  // 正在后台刷新的键, 避免重复刷新
+  refreshing: DashMap<FastStr, ()>,
+  // 进行中的上游请求
+  flight: DashMap<FastStr, Arc<Flight>>,
+  // 最近的清除记录 (时间, 条件), 在此之前发出的上游请求不再存入
+  ban_li: Mutex<Vec<(u64, Purge)>>,
+}
+
+impl Default for Cache {
+  fn default() -> Self {
+    Self {
+      lru: Mutex::new(Lru::default()),
+      max_byte: AtomicU64::new(256 << 20),
+      refreshing: DashMap::new(),
+      flight: DashMap::new(),
+      ban_li: Mutex::new(Vec::new()),
+    }
+  }
+}
+
+impl Cache {
+  pub fn set_max_byte(&self, max_byte: u64) {
+    self.max_byte.store(max_byte, Relaxed);
+  }
+
+  /// 已用字节数
+  pub fn byte(&self) -> u64 {
+    self.lru.lock().byte
+  }
+
+  pub fn len(&self) -> usize {
+    self.lru.lock().len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn get(&self, key: &str, req_headers: &HeaderMap) -> Option<Arc<Entry>> {
+    let mut lru = self.lru.lock();
+    lru
+      .get(key)?
+      .iter()
+      .find(|e| e.is_match(req_headers))
+      .cloned()
+  }
+
+  pub fn put(&self, key: FastStr, entry: Arc<Entry>) {
+    let max_byte = self.max_byte.load(Relaxed);
+    self.lru.lock().put(key, entry, max_byte);
+  }
+
+  pub fn remove(&self, key: &str) -> bool {
+    self.lru.lock().remove(key)
+  }
+
+  /// 清除符合条件的缓存, 返回清除的份数
+  /// 清除前已发出的上游请求, 响应也不会再存入
+  pub fn purge(&self, purge: Purge) -> usize {
+    let now = now_ms();
+    {
+      let mut ban_li = self.ban_li.lock();
+      ban_li.retain(|(time, _)| time + BAN_KEEP_MS > now);
+      ban_li.push((now, purge.clone()));
+    }
+    self.lru.lock().retain(|key, e| !purge.is_match(key, e))
+  }
+
+  // 从 since 开始的上游请求得到的响应, 存入前检查是否已被清除
+  fn put_since(&self, key: FastStr, entry: Arc<Entry>, since: u64) {
+    let banned = self
+      .ban_li
+      .lock()
+      .iter()
+      .any(|(time, purge)| *time > since && purge.is_match(&key, &entry));
+    if !banned {
+      self.put(key, entry);
+    }
+  }
+
+  // 合并请求的键: 缓存键加上可能影响响应的请求头
+  fn flight_key(&self, key: &str, headers: &HeaderMap) -> FastStr {
+    let mut name_li = vec![
+      ACCEPT_ENCODING,
+      ACCEPT_LANGUAGE,
+      COOKIE,
+      AUTHORIZATION,
+      IF_NONE_MATCH,
+      IF_MODIFIED_SINCE,
+    ];
+    for name in self.lru.lock().vary_of(key) {
+      if !name_li.contains(&name) {
+        name_li.push(name);
+      }
+    }
+    let mut flight_key = key.to_owned();
+    for name in name_li {
+      for v in headers.get_all(&name) {
+        flight_key.push_str(&format!(
+          "\n{name}:{}",
+          String::from_utf8_lossy(v.as_bytes())
+        ));
+      }
+    }
+    flight_key.into()
+  }
+}
+
+/// 先查缓存, 未命中或过期时调用 fetch 请求上游, 可缓存的响应边转发边存入
+pub async fn fetch<F, Fut>(
+  cache: &Arc<Cache>,
+  rule: &Arc<CacheRule>,
+  key: FastStr,
+  parts: Parts,
+  fetch: F,
+) -> Result<Res>
+where
+  F: FnOnce(Parts) -> Fut + Send + 'static,
+  Fut: Future<Output = Result<Res>> + Send + 'static,
+{
+  let head = parts.method == Method::HEAD;
+  let req_cc = CacheControl::parse(&parts.headers);
+  let entry = (!(req_cc.no_cache || req_cc.no_store))
+    .then(|| cache.get(&key, &parts.headers))
+    .flatten();
+  if let Some(entry) = &entry {
+    let now = now_ms();
+    if entry.is_fresh(now) {
+      return Ok(entry.response(&parts.headers, head));
+    }
+    // 过期不久, 先返回旧内容, 后台刷新
+    if entry.can_revalidate_stale(now) {
+      let res = stale(entry.response(&parts.headers, head));
+      if cache.refreshing.insert(key.clone(), ()).is_none() {
+        let cache = cache.clone();
+        let rule = rule.clone();
+        let entry = entry.clone();
+        let mut parts = parts;
+        parts.method = Method::GET;
+        tokio::spawn(async move {
+          match update(&cache, &rule, key.clone(), Some(entry), parts, fetch).await {
+            // 读完响应体才会存入缓存
+            Ok(res) => {
+              if let Err(err) = res.into_body().collect().await {
+                log::warn!("cache refresh {key}: {err}");
+              }
+            }
+            Err(err) => log::warn!("cache refresh {key}: {err}"),
+          }
+          cache.refreshing.remove(&key);
+        });
+      }
+      return Ok(res);
+    }
+  }
+
+  let req_headers = parts.headers.clone();
+  let r = if head || rule.flight_timeout_sec == 0 {
+    update(cache, rule, key, entry.clone(), parts, fetch).await
+  } else {
+    fly(cache, rule, key, entry.clone(), parts, fetch).await
+  };
+  // 上游出错时返回旧内容
+  if let Some(entry) = entry
+    && entry.can_error_stale(now_ms())
+  {
+    match &r {
+      Err(err) => log::warn!("serve stale: {err}"),
+      Ok(res) if res.status().is_server_error() => {}
+      _ => return r,
+    }
+    return Ok(stale(entry.response(&req_headers, head)));
+  }
+  r
+}
+
+// 相同的并发请求只有第一个请求上游, 其余共享它的响应, 等待超时后各自请求
+async fn fly<F, Fut>(
+  cache: &Arc<Cache>,
+  rule: &CacheRule,
+  key: FastStr,
+  entry: Option<Arc<Entry>>,
+  parts: Parts,
+  fetch: F,
+) -> Result<Res>
+where
+  F: FnOnce(Parts) -> Fut,
+  Fut: Future<Output = Result<Res>>,
+{
+  let flight_key = cache.flight_key(&key, &parts.headers);
+  let lead = match cache.flight.entry(flight_key.clone()) {
+    MapEntry::Occupied(e) => Err(e.get().clone()),
+    MapEntry::Vacant(e) => {
+      let flight = Arc::new(Flight::default());
+      e.insert(flight.clone());
+      Ok(flight)
+    }
+  };
+  let flight = match lead {
+    Ok(flight) => flight,
+    Err(flight) => {
+      if flight.joinable(rule.max_body)
+        && let Some(res) = flight
+          .follow(Duration::from_secs(rule.flight_timeout_sec))
+          .await
+      {
+        return Ok(res);
+      }
+      return update(cache, rule, key, entry, parts, fetch).await;
+    }
+  };
+  // 领头请求出错或被取消时让跟随者各自请求
+  let mut guard = Abort {
+    cache,
+    flight_key: flight_key.clone(),
+    flight: flight.clone(),
+    armed: true,
+  };
+  let req_headers = parts.headers.clone();
+  let res = update(cache, rule, key, entry, parts, fetch).await?;
+  // 不可缓存的响应 (如 no-store, private, 带 Set-Cookie) 不能给别人用, 太大的不缓冲, 让跟随者各自请求
+  let length = res
+    .headers()
+    .get(CONTENT_LENGTH)
+    .and_then(|v| v.to_str().ok())
+    .and_then(|v| v.parse::<u64>().ok());
+  if rule
+    .ttl_of(res.status(), res.headers(), &req_headers)
+    .is_none()
+    || length.is_some_and(|n| n > rule.max_body)
+  {
+    return Ok(res);
+  }
+  guard.armed = false;
+  let cache = cache.clone();
+  Ok(flight.lead(res, rule.max_body, move || {
+    cache.flight.remove(&flight_key);
+  }))
+}
+
+struct Abort<'a> {
+  cache: &'a Cache,
+  flight_key: FastStr,
+  flight: Arc<Flight>,
+  armed: bool,
+}
+
+impl Drop for Abort<'_> {
+  fn drop(&mut self) {
+    if self.armed {
+      self.flight.fail();
+      self.cache.flight.remove(&self.flight_key);
+    }
+  }
+}
+
+// 请求上游, 有旧内容时发条件请求, 可缓存的响应边转发边存入
+async fn update<F, Fut>(
+  cache: &Arc<Cache>,
+  rule: &CacheRule,
+  key: FastStr,
+  entry: Option<Arc<Entry>>,
+  mut parts: Parts,
+  fetch: F,
+) -> Result<Res>
+where
+  F: FnOnce(Parts) -> Fut,
+  Fut: Future<Output = Result<Res>>,
+{
+  let head = parts.method == Method::HEAD;
+  let req_headers = parts.headers.clone();
+  // 过期但有校验器, 向上游发条件请求
+  let entry = entry.filter(|e| e.has_validator());
+  if let Some(entry) = &entry {
+    parts.headers.remove(IF_MODIFIED_SINCE);
+    parts.headers.remove(IF_NONE_MATCH);
+    if let Some(etag) = entry.headers.get(ETAG) {
+      parts.headers.insert(IF_NONE_MATCH, etag.clone());
+    }
+    if let Some(last) = entry.headers.get(LAST_MODIFIED) {
+      parts.headers.insert(IF_MODIFIED_SINCE, last.clone());
+    }
+  }
+
+  let since = now_ms();
+  let mut res = fetch(parts).await?;
+  let tag_li = match &rule.tag_header {
+    Some(name) => {
+      let li = tag_li(res.headers(), name);
+      res.headers_mut().remove(name);
+      li
+    }
+    None => Box::default(),
+  };
+
+  if let Some(entry) = entry
+    && res.status() == StatusCode::NOT_MODIFIED
+  {
+    let entry = Arc::new(entry.refresh(res.headers(), rule, &req_headers));
+    cache.put_since(key, entry.clone(), since);
+    return Ok(entry.response(&req_headers, head));
+  }
+
+  if head {
+    return Ok(res);
+  }
+  let Some(ttl) = rule.ttl_of(res.status(), res.headers(), &req_headers) else {
+    return Ok(res);
+  };
+  let Some(vary) = vary_li(res.headers()) else {
+    return Ok(res);
+  };
+  let vary = vary
+    .into_iter()
+    .map(|name| {
+      let v = req_headers.get(&name).cloned();
+      (name, v)
+    })
+    .collect();
+  let (stale_revalidate, stale_error) = rule.stale_of(res.headers());
+
+  let (parts, body) = res.into_parts();
+  let status = parts.status;
+  let headers = parts.headers.clone();
+  let cache = cache.clone();
+  let body = Tee::new(body, rule.max_body, move |body| {
+    cache.put_since(
+      key,
+      Arc::new(Entry {
+        status,
+        headers,
+        body,
+        time: now_ms(),
+        ttl,
+        stale_revalidate,
+        stale_error,
+        vary,
+        tag_li,
+      }),
+      since,
+    );
+  });
+  Ok(Response::from_parts(parts, body.boxed()))
+}
@@ -0,0 +1,81 @@
+use std::{
+  pin::Pin,
+  task::{Context, Poll},
+};
+
+use bytes::{Bytes, BytesMut};
+use http_body::{Body, Frame, SizeHint};
+use http_body_util::combinators::BoxBody;
+
+use crate::Error;
+
+type Done = Box<dyn FnOnce(Bytes) + Send + Sync>;
+
+/// 边转发边收集响应体, 完整读完且不超过 max 时回调 done
+pub struct Tee {
+  inner: BoxBody<Bytes, Error>,
+  buf: BytesMut,
+  max: u64,
+  over: bool,
+  done: Option<Done>,
+}
+
+impl Tee {
+  pub fn new(
+    inner: BoxBody<Bytes, Error>,
+    max: u64,
+    done: impl FnOnce(Bytes) + Send + Sync + 'static,
+  ) -> Self {
+    Self {
+      inner,
+      buf: BytesMut::new(),
+      max,
+      over: false,
+      done: Some(Box::new(done)),
+    }
+  }
+}
+
+impl Body for Tee {
+  type Data = Bytes;
+  type Error = Error;
+
+  fn poll_frame(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<Option<Result<Frame<Bytes>, Error>>> {
+    let this = &mut *self;
+    match Pin::new(&mut this.inner).poll_frame(cx) {
+      Poll::Ready(Some(Ok(frame))) => {
+        if let Some(data) = frame.data_ref()
+          && !this.over
+        {
+          if (this.buf.len() + data.len()) as u64 > this.max {
+            this.over = true;
+            this.buf = BytesMut::new();
+          } else {
+            this.buf.extend_from_slice(data);
+          }
+        }
+        Poll::Ready(Some(Ok(frame)))
+      }
+      Poll::Ready(None) => {
+        if !this.over
+          && let Some(done) = this.done.take()
+        {
+          done(this.buf.split().freeze());
+        }
+        Poll::Ready(None)
+      }
+      other => other,
+    }
+  }
+
+  fn is_end_stream(&self) -> bool {
+    self.inner.is_end_stream()
+  }
+
+  fn size_hint(&self) -> SizeHint {
+    self.inner.size_hint()
+  }
+}
@@ -11,6 +11,7 @@ use x509_parser::{extensions::GeneralName, parse_x509_certificate};
 use crate::{
   cert::Cert,
   error::{Error, Result},
+  normalize_host,
 };
 
 #[cfg(feature = "cert_dir")]
@@ -110,8 +111,8 @@ impl<L: CertLoad> CertLoader<L> {
     Ok(None)
   }
 
-  pub async fn get(&self, host: impl Into<String>) -> Result<Arc<Cert>> {
-    let host = host.into();
+  pub async fn get(&self, host: impl AsRef<str>) -> Result<Arc<Cert>> {
+    let host = normalize_host(host.as_ref());
 
     if let Some(cert) = self.cert_by_host(host.clone()).await? {
       return Ok(cert);
@@ -122,6 +123,6 @@ impl<L: CertLoad> CertLoader<L> {
     {
       return Ok(cert);
     }
-    Err(Error::CertNotFound(host))
+    Err(Error::CertNotFound(host.to_string()))
   }
 }
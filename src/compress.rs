@@ -0,0 +1,205 @@
+use std::{future::ready, io, pin::Pin};
+
+use async_compression::{
+  Level,
+  tokio::bufread::{BrotliEncoder, GzipEncoder, ZstdEncoder},
+};
+use faststr::FastStr;
+use futures_util::StreamExt;
+use http::{
+  HeaderMap, HeaderValue, Response, StatusCode,
+  header::{
+    ACCEPT_RANGES, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, ETAG, VARY,
+  },
+};
+use http_body::Frame;
+use http_body_util::{BodyDataStream, BodyExt, StreamBody, combinators::BoxBody};
+use hyper::body::Bytes;
+use tokio::io::AsyncRead;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+use crate::Error;
+
+/// 压缩算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+  Zstd,
+  Br,
+  Gzip,
+}
+
+impl Encoding {
+  pub fn name(&self) -> &'static str {
+    match self {
+      Encoding::Zstd => "zstd",
+      Encoding::Br => "br",
+      Encoding::Gzip => "gzip",
+    }
+  }
+}
+
+/// 站点的响应压缩配置
+#[derive(Debug, Clone)]
+pub struct Compress {
+  // Content-Length 小于该值时不压缩, 长度未知时总是压缩
+  pub min_size: u64,
+  // 可压缩的 Content-Type 前缀
+  pub type_li: Box<[FastStr]>,
+  // 客户端权重相同时按此顺序优先
+  pub encoding_li: Box<[Encoding]>,
+}
+
+impl Default for Compress {
+  fn default() -> Self {
+    Self {
+      min_size: 1024,
+      type_li: [
+        "text/",
+        "application/json",
+        "application/javascript",
+        "application/xml",
+        "application/wasm",
+        "image/svg+xml",
+      ]
+      .map(FastStr::from_static_str)
+      .into(),
+      encoding_li: [Encoding::Zstd, Encoding::Br, Encoding::Gzip].into(),
+    }
+  }
+}
+
+// Accept-Encoding 中 name 的权重, 未列出时返回 None
+fn q_of(accept: &str, name: &str) -> Option<f32> {
+  accept.split(',').find_map(|item| {
+    let mut it = item.split(';');
+    it.next()?.trim().eq_ignore_ascii_case(name).then(|| {
+      it.find_map(|p| p.trim().strip_prefix("q="))
+        .and_then(|q| q.parse().ok())
+        .unwrap_or(1.0)
+    })
+  })
+}
+
+impl Compress {
+  /// 按 Accept-Encoding 选择压缩算法, 客户端都不接受时返回 None
+  pub fn negotiate(&self, accept: &str) -> Option<Encoding> {
+    let mut best: Option<(f32, Encoding)> = None;
+    for &encoding in self.encoding_li.iter() {
+      let q = q_of(accept, encoding.name())
+        .or_else(|| q_of(accept, "*"))
+        .unwrap_or(0.0);
+      if q > 0.0 && best.is_none_or(|(b, _)| q > b) {
+        best = Some((q, encoding));
+      }
+    }
+    best.map(|(_, encoding)| encoding)
+  }
+
+  // 响应是否可以压缩, 不看客户端是否接受
+  fn eligible(&self, status: StatusCode, headers: &HeaderMap) -> bool {
+    if status.is_informational()
+      || status.is_redirection()
+      || status == StatusCode::NO_CONTENT
+      || status == StatusCode::PARTIAL_CONTENT
+      || headers.contains_key(CONTENT_ENCODING)
+    {
+      return false;
+    }
+    let header = |name| {
+      headers
+        .get(name)
+        .and_then(|v: &HeaderValue| v.to_str().ok())
+    };
+    if header(CACHE_CONTROL).is_some_and(|v| v.to_ascii_lowercase().contains("no-transform")) {
+      return false;
+    }
+    let Some(content_type) = header(CONTENT_TYPE).map(|v| v.to_ascii_lowercase()) else {
+      return false;
+    };
+    // 事件流需要逐条送达, 压缩会缓冲
+    if content_type.starts_with("text/event-stream") {
+      return false;
+    }
+    if !self
+      .type_li
+      .iter()
+      .any(|t| content_type.starts_with(t.as_str()))
+    {
+      return false;
+    }
+    header(CONTENT_LENGTH)
+      .and_then(|v| v.parse::<u64>().ok())
+      .is_none_or(|len| len >= self.min_size)
+  }
+
+  /// 按请求的 Accept-Encoding 压缩响应, 流式压缩, 不缓冲整个响应体
+  pub fn apply(
+    &self,
+    accept: Option<&HeaderValue>,
+    res: Response<BoxBody<Bytes, Error>>,
+  ) -> Response<BoxBody<Bytes, Error>> {
+    if !self.eligible(res.status(), res.headers()) {
+      return res;
+    }
+    let (mut parts, body) = res.into_parts();
+    let headers = &mut parts.headers;
+    let has_vary = headers
+      .get_all(VARY)
+      .iter()
+      .filter_map(|v| v.to_str().ok())
+      .flat_map(|v| v.split(','))
+      .any(|v| {
+        let v = v.trim();
+        v == "*" || v.eq_ignore_ascii_case("accept-encoding")
+      });
+    if !has_vary {
+      headers.append(VARY, HeaderValue::from_static("Accept-Encoding"));
+    }
+
+    let Some(encoding) = accept
+      .and_then(|v| v.to_str().ok())
+      .and_then(|v| self.negotiate(v))
+    else {
+      return Response::from_parts(parts, body);
+    };
+
+    headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.name()));
+    headers.remove(CONTENT_LENGTH);
+    headers.remove(ACCEPT_RANGES);
+    // 压缩后内容与原 ETag 不再逐字节一致, 改为弱校验
+    if let Some(etag) = headers.get(ETAG)
+      && !etag.as_bytes().starts_with(b"W/")
+      && let Ok(weak) = HeaderValue::from_bytes(&[b"W/", etag.as_bytes()].concat())
+    {
+      headers.insert(ETAG, weak);
+    }
+    Response::from_parts(parts, encode(encoding, body))
+  }
+}
+
+fn encode(encoding: Encoding, body: BoxBody<Bytes, Error>) -> BoxBody<Bytes, Error> {
+  let reader = StreamReader::new(BodyDataStream::new(body).map(|r| r.map_err(io::Error::other)));
+  let reader: Pin<Box<dyn AsyncRead + Send + Sync>> = match encoding {
+    Encoding::Zstd => Box::pin(ZstdEncoder::new(reader)),
+    // brotli 默认级别太慢, 不适合实时压缩
+    Encoding::Br => Box::pin(BrotliEncoder::with_quality(reader, Level::Precise(5))),
+    Encoding::Gzip => Box::pin(GzipEncoder::new(reader)),
+  };
+  let stream = ReaderStream::new(reader).filter_map(|r| {
+    ready(match r {
+      Ok(data) => Some(Ok(Frame::data(data))),
+      // 上游的错误原样传出, 压缩自身的错误只能截断响应
+      Err(err) => {
+        let msg = err.to_string();
+        match err.into_inner().map(|e| e.downcast::<Error>()) {
+          Some(Ok(err)) => Some(Err(*err)),
+          _ => {
+            log::warn!("compress: {msg}");
+            None
+          }
+        }
+      }
+    })
+  });
+  BodyExt::boxed(StreamBody::new(stream))
+}
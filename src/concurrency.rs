@@ -0,0 +1,183 @@
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use tokio::{sync::Notify, time::Instant};
+
+use crate::{Error, Result};
+
+// 每隔这么多个样本, 用窗口内的最小延迟更新基准, 让基准能随后端变慢而升高
+const WINDOW: u32 = 256;
+
+#[derive(Debug)]
+struct State {
+  limit: f64,
+  in_flight: usize,
+  queue: usize,
+  // 基准延迟, 毫秒, 0 表示还没有样本
+  min_rtt: f64,
+  window_min: f64,
+  sample: u32,
+}
+
+/// 上游的自适应并发限制 (AIMD)
+/// 延迟不超过基准的 tolerance 倍时逐步放宽, 超过或出错时按 backoff 收紧
+/// 超出并发的请求排队等待, 队列满或等待超时时返回 Error::Overloaded
+#[derive(Debug)]
+pub struct Concurrency {
+  pub min_limit: usize,
+  pub max_limit: usize,
+  // 排队的最大请求数, 0 表示不排队
+  pub max_queue: usize,
+  pub queue_timeout: Duration,
+  pub tolerance: f64,
+  pub backoff: f64,
+  state: Mutex<State>,
+  notify: Notify,
+}
+
+impl Concurrency {
+  pub fn new(initial: usize, min_limit: usize, max_limit: usize) -> Self {
+    let min_limit = min_limit.max(1);
+    // 上限小于下限时取下限, 避免调整时 clamp 越界
+    let max_limit = max_limit.max(min_limit);
+    Self {
+      min_limit,
+      max_limit,
+      max_queue: 100,
+      queue_timeout: Duration::from_secs(5),
+      tolerance: 2.0,
+      backoff: 0.9,
+      state: Mutex::new(State {
+        limit: initial.clamp(min_limit, max_limit) as f64,
+        in_flight: 0,
+        queue: 0,
+        min_rtt: 0.0,
+        window_min: f64::MAX,
+        sample: 0,
+      }),
+      notify: Notify::new(),
+    }
+  }
+
+  /// 当前的并发上限
+  pub fn limit(&self) -> usize {
+    self.state.lock().limit as usize
+  }
+
+  /// 进行中的请求数
+  pub fn in_flight(&self) -> usize {
+    self.state.lock().in_flight
+  }
+
+  /// 排队中的请求数
+  pub fn queue(&self) -> usize {
+    self.state.lock().queue
+  }
+
+  fn try_take(&self, state: &mut State) -> bool {
+    if state.in_flight < state.limit as usize {
+      state.in_flight += 1;
+      return true;
+    }
+    false
+  }
+
+  /// 取得并发许可, 需要时排队等待
+  pub async fn acquire(&self) -> Result<Permit<'_>> {
+    {
+      let mut state = self.state.lock();
+      if self.try_take(&mut state) {
+        return Ok(Permit::new(self));
+      }
+      if state.queue >= self.max_queue {
+        return Err(Error::Overloaded);
+      }
+      state.queue += 1;
+    }
+    // 被取消时也要离开队列
+    let _queued = Queued(self);
+    let deadline = Instant::now() + self.queue_timeout;
+    loop {
+      // 先登记等待再检查, 检查后的唤醒不会丢失
+      let notified = self.notify.notified();
+      tokio::pin!(notified);
+      notified.as_mut().enable();
+      if self.try_take(&mut self.state.lock()) {
+        return Ok(Permit::new(self));
+      }
+      if tokio::time::timeout_at(deadline, notified).await.is_err() {
+        return Err(Error::Overloaded);
+      }
+    }
+  }
+
+  // 记录一次请求的结果, 调整并发上限
+  fn sample(&self, rtt: Duration, ok: bool) {
+    let mut state = self.state.lock();
+    let rtt = rtt.as_secs_f64() * 1000.0;
+    if ok {
+      state.window_min = state.window_min.min(rtt);
+      state.sample += 1;
+      if state.min_rtt == 0.0 {
+        state.min_rtt = rtt;
+      } else if state.sample >= WINDOW {
+        state.min_rtt = state.window_min;
+        state.window_min = f64::MAX;
+        state.sample = 0;
+      }
+      state.min_rtt = state.min_rtt.min(rtt);
+    }
+    let limit = if ok && rtt <= state.min_rtt.max(1.0) * self.tolerance {
+      state.limit + 1.0 / state.limit
+    } else {
+      state.limit * self.backoff
+    };
+    let old = state.limit as usize;
+    state.limit = limit.clamp(self.min_limit as f64, self.max_limit as f64);
+    // 上限变大时唤醒排队的请求
+    let more = (state.limit as usize).saturating_sub(old);
+    drop(state);
+    for _ in 0..more {
+      self.notify.notify_one();
+    }
+  }
+
+  fn release(&self) {
+    self.state.lock().in_flight -= 1;
+    self.notify.notify_one();
+  }
+}
+
+struct Queued<'a>(&'a Concurrency);
+
+impl Drop for Queued<'_> {
+  fn drop(&mut self) {
+    self.0.state.lock().queue -= 1;
+  }
+}
+
+/// 并发许可, 释放时唤醒一个排队的请求
+pub struct Permit<'a> {
+  concurrency: &'a Concurrency,
+  start: Instant,
+}
+
+impl<'a> Permit<'a> {
+  fn new(concurrency: &'a Concurrency) -> Self {
+    Self {
+      concurrency,
+      start: Instant::now(),
+    }
+  }
+
+  /// 请求结束, 按耗时和是否成功调整并发上限
+  pub fn done(self, ok: bool) {
+    self.concurrency.sample(self.start.elapsed(), ok);
+  }
+}
+
+impl Drop for Permit<'_> {
+  fn drop(&mut self) {
+    self.concurrency.release();
+  }
+}
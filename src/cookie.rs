@@ -0,0 +1,14 @@
+use http::{HeaderMap, header::COOKIE};
+
+/// 从请求头中读取 cookie 的值
+pub fn get<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+  headers
+    .get_all(COOKIE)
+    .iter()
+    .filter_map(|v| v.to_str().ok())
+    .flat_map(|v| v.split(';'))
+    .find_map(|kv| {
+      let (k, v) = kv.split_once('=')?;
+      (k.trim() == name).then(|| v.trim())
+    })
+}
@@ -1,3 +1,4 @@
+use http::StatusCode;
 use s2n_quic::provider::StartError;
 use thiserror::Error;
 
@@ -36,9 +37,18 @@ pub enum Error {
   #[error("UpstreamNotFound")]
   UpstreamNotFound,
 
+  #[error("Overloaded")]
+  Overloaded,
+
   #[error("InvalidHost: {0}")]
   InvalidHost(#[from] hyper::http::uri::InvalidUri),
 
+  #[error("InvalidUriParts: {0}")]
+  InvalidUriParts(#[from] hyper::http::uri::InvalidUriParts),
+
+  #[error("InvalidHeaderValue: {0}")]
+  InvalidHeaderValue(#[from] http::header::InvalidHeaderValue),
+
   #[error("TokioJoin: {0}")]
   TokioJoin(#[from] tokio::task::JoinError),
 
@@ -57,6 +67,9 @@ pub enum Error {
   #[error("S2nQuicStart: {0}")]
   S2nQuicStart(#[from] StartError),
 
+  #[error("S2nQuicConnection: {0}")]
+  S2nQuicConnection(#[from] s2n_quic::connection::Error),
+
   #[error("S2nQuicTls: {0}")]
   S2nQuicTls(#[from] s2n_quic::provider::tls::s2n_tls::error::Error),
 
@@ -68,6 +81,33 @@ pub enum Error {
 
   #[error("ListenerNotFound: {0}")]
   ListenerNotFound(std::net::SocketAddr),
+
+  #[error("RedirectParse: {0}")]
+  RedirectParse(String),
+
+  #[error("Tpl: {0}")]
+  Tpl(String),
+
+  #[error("FlightAbort")]
+  FlightAbort,
+
+  #[cfg(feature = "redis")]
+  #[error("Redis: {0}")]
+  Redis(#[from] redis::RedisError),
+
+  #[cfg(feature = "redis")]
+  #[error("RedisTimeout")]
+  RedisTimeout,
+}
+
+impl Error {
+  /// 返回给客户端的状态码
+  pub fn status(&self) -> StatusCode {
+    match self {
+      Error::Overloaded => StatusCode::SERVICE_UNAVAILABLE,
+      _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+  }
 }
 
 pub trait IntoError {
@@ -0,0 +1,103 @@
+use faststr::FastStr;
+use http::{HeaderMap, HeaderName, HeaderValue};
+
+use crate::{Result, Tpl, Vars};
+
+/// 规则作用于转发给上游的请求还是返回给客户端的响应
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderSide {
+  Req,
+  Res,
+}
+
+#[derive(Debug, Clone)]
+pub enum HeaderOp {
+  // 追加, 保留已有的值
+  Add(Tpl),
+  // 覆盖已有的值
+  Set(Tpl),
+  // 不存在时才设置
+  Default(Tpl),
+  Remove,
+}
+
+/// 请求头和响应头的增删改规则
+#[derive(Debug, Clone)]
+pub struct HeaderRule {
+  pub side: HeaderSide,
+  pub name: HeaderName,
+  pub op: HeaderOp,
+  // 只对该路径前缀生效, None 时对整个站点生效
+  pub path: Option<FastStr>,
+}
+
+impl HeaderRule {
+  pub fn req(name: HeaderName, op: HeaderOp) -> Self {
+    Self {
+      side: HeaderSide::Req,
+      name,
+      op,
+      path: None,
+    }
+  }
+
+  pub fn res(name: HeaderName, op: HeaderOp) -> Self {
+    Self {
+      side: HeaderSide::Res,
+      name,
+      op,
+      path: None,
+    }
+  }
+
+  pub fn path(mut self, prefix: impl Into<FastStr>) -> Self {
+    self.path = Some(prefix.into());
+    self
+  }
+}
+
+/// 生成请求 id
+pub fn request_id() -> String {
+  format!("{:016x}", fastrand::u64(..))
+}
+
+/// 按顺序执行 side 一侧的规则
+pub fn apply(
+  rule_li: &[HeaderRule],
+  side: HeaderSide,
+  headers: &mut HeaderMap,
+  vars: &Vars,
+) -> Result<()> {
+  let path = vars
+    .path_and_query
+    .split_once('?')
+    .map_or(vars.path_and_query, |(p, _)| p);
+  for rule in rule_li {
+    if rule.side != side
+      || rule
+        .path
+        .as_ref()
+        .is_some_and(|prefix| !path.starts_with(prefix.as_str()))
+    {
+      continue;
+    }
+    let name = rule.name.clone();
+    match &rule.op {
+      HeaderOp::Add(tpl) => {
+        headers.append(name, HeaderValue::from_str(&tpl.render(vars))?);
+      }
+      HeaderOp::Set(tpl) => {
+        headers.insert(name, HeaderValue::from_str(&tpl.render(vars))?);
+      }
+      HeaderOp::Default(tpl) => {
+        if !headers.contains_key(&name) {
+          headers.insert(name, HeaderValue::from_str(&tpl.render(vars))?);
+        }
+      }
+      HeaderOp::Remove => {
+        headers.remove(name);
+      }
+    }
+  }
+  Ok(())
+}
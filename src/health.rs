@@ -0,0 +1,32 @@
+use std::net::SocketAddr;
+
+use coarsetime::Clock;
+use dashmap::DashMap;
+
+/// 被动健康检查: 连接失败的地址在一段时间内视为不可用
+#[derive(Debug, Default)]
+pub struct Health {
+  // 地址 -> 恢复时间 (秒)
+  down: DashMap<SocketAddr, u64>,
+}
+
+impl Health {
+  pub fn fail(&self, addr: SocketAddr, sec: u64) {
+    self
+      .down
+      .insert(addr, Clock::now_since_epoch().as_secs() + sec);
+  }
+
+  pub fn ok(&self, addr: SocketAddr) {
+    if !self.down.is_empty() {
+      self.down.remove(&addr);
+    }
+  }
+
+  pub fn is_up(&self, addr: SocketAddr) -> bool {
+    match self.down.get(&addr) {
+      Some(until) => *until <= Clock::now_since_epoch().as_secs(),
+      None => true,
+    }
+  }
+}
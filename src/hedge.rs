@@ -0,0 +1,114 @@
+use std::{
+  collections::VecDeque,
+  sync::atomic::{AtomicU64, Ordering::Relaxed},
+  time::Duration,
+};
+
+use http::Method;
+use parking_lot::Mutex;
+use tokio::time::Instant;
+
+// 保留最近的延迟样本数
+const SAMPLE_CAP: usize = 1024;
+// 每记录这么多个样本重新计算一次分位数
+const UPDATE_EVERY: usize = 32;
+
+/// 对冲请求: 超过延迟分位数还没有响应时, 向另一个地址再发一次, 用先成功的响应, 取消另一个
+#[derive(Debug)]
+pub struct Hedge {
+  // 如 0.95, 延迟超过最近 95% 的请求时对冲
+  pub percentile: f64,
+  pub min_delay: Duration,
+  // 样本不足时也用该值
+  pub max_delay: Duration,
+  // 只对冲这些方法, 应为幂等方法
+  pub method_li: Box<[Method]>,
+  // 最近的延迟, 毫秒
+  sample: Mutex<VecDeque<u64>>,
+  delay_ms: AtomicU64,
+}
+
+impl Hedge {
+  pub fn new(percentile: f64) -> Self {
+    let max_delay = Duration::from_secs(1);
+    Self {
+      percentile,
+      min_delay: Duration::from_millis(5),
+      max_delay,
+      method_li: [Method::GET, Method::HEAD, Method::OPTIONS].into(),
+      sample: Mutex::new(VecDeque::with_capacity(SAMPLE_CAP)),
+      delay_ms: AtomicU64::new(max_delay.as_millis() as u64),
+    }
+  }
+
+  pub fn is_hedgeable(&self, method: &Method) -> bool {
+    self.method_li.contains(method)
+  }
+
+  /// 发出对冲请求前等待的时长
+  pub fn delay(&self) -> Duration {
+    Duration::from_millis(self.delay_ms.load(Relaxed)).clamp(self.min_delay, self.max_delay)
+  }
+
+  /// 记录一次请求的延迟
+  pub fn record(&self, latency: Duration) {
+    let mut sample = self.sample.lock();
+    if sample.len() == SAMPLE_CAP {
+      sample.pop_front();
+    }
+    sample.push_back(latency.as_millis() as u64);
+    let len = sample.len();
+    if len.is_multiple_of(UPDATE_EVERY) || len == SAMPLE_CAP {
+      let mut li: Vec<u64> = sample.iter().copied().collect();
+      drop(sample);
+      let nth = ((len as f64 * self.percentile) as usize).min(len - 1);
+      let (_, ms, _) = li.select_nth_unstable(nth);
+      self.delay_ms.store(*ms, Relaxed);
+    }
+  }
+
+  /// 先向 pos 发请求, 超过 delay 还没有响应时再向 next 发一次
+  /// 返回先成功的地址下标和结果, 都失败时返回后失败的
+  pub async fn race<T, E, F, Fut>(&self, pos: usize, next: usize, send: F) -> (usize, Result<T, E>)
+  where
+    F: Fn(usize) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+  {
+    // 延迟从发出第一个请求算起, 即客户端看到的延迟
+    // 对冲请求胜出时第一个请求被取消, 只按各自耗时记录会偏低
+    let start = Instant::now();
+    let timed = |pos: usize| {
+      let fut = send(pos);
+      async move {
+        let r = fut.await;
+        if r.is_ok() {
+          self.record(start.elapsed());
+        }
+        r
+      }
+    };
+    let first = timed(pos);
+    tokio::pin!(first);
+    tokio::select! {
+      r = &mut first => return (pos, r),
+      _ = tokio::time::sleep(self.delay()) => {}
+    }
+    if next == pos {
+      return (pos, first.await);
+    }
+    log::info!("hedge: {pos} -> {next}");
+    let second = timed(next);
+    tokio::pin!(second);
+    // 返回时丢弃的请求即被取消
+    tokio::select! {
+      r = &mut first => match r {
+        Ok(_) => (pos, r),
+        Err(_) => (next, second.await),
+      },
+      r = &mut second => match r {
+        Ok(_) => (next, r),
+        Err(_) => (pos, first.await),
+      },
+    }
+  }
+}
@@ -0,0 +1,34 @@
+use std::borrow::Cow;
+
+use faststr::FastStr;
+
+/// 规范化域名: 去掉端口和末尾的点, 转小写, 国际化域名转为 punycode
+pub fn normalize(host: &str) -> FastStr {
+  let host = host.trim();
+
+  // IPv6 字面量, 如 [::1]:443
+  if host.starts_with('[') {
+    let end = host.find(']').map_or(host.len(), |p| p + 1);
+    return FastStr::new(host[..end].to_ascii_lowercase());
+  }
+
+  let host = match host.rsplit_once(':') {
+    Some((h, port)) if !h.contains(':') && port.bytes().all(|b| b.is_ascii_digit()) => h,
+    _ => host,
+  };
+  let host = host.strip_suffix('.').unwrap_or(host);
+
+  let host = if host.is_ascii() {
+    if host.bytes().any(|b| b.is_ascii_uppercase()) {
+      Cow::Owned(host.to_ascii_lowercase())
+    } else {
+      Cow::Borrowed(host)
+    }
+  } else {
+    match idna::domain_to_ascii(host) {
+      Ok(ascii) => Cow::Owned(ascii),
+      Err(_) => Cow::Owned(host.to_lowercase()),
+    }
+  };
+  FastStr::new(host)
+}
@@ -1,23 +1,78 @@
+use faststr::FastStr;
+
+mod admin;
+mod alt_svc;
+mod cache;
 mod cert;
 mod cert_loader;
+mod compress;
+mod concurrency;
+mod cookie;
 mod error;
+mod header_rule;
+mod health;
+mod hedge;
+mod host;
+mod locale;
+mod mirror;
+mod park;
+mod peer;
 mod proxy;
+mod rate_limit;
+mod redirect;
+#[cfg(feature = "redis")]
+mod redis_store;
+mod res_rewrite;
+mod retry;
 mod route;
+mod rule;
+mod sec_header;
 pub mod shutdown;
+mod split;
 pub mod srv;
+mod sticky;
+mod tpl;
 
+pub use admin::Admin;
+pub use alt_svc::AltSvc;
+pub use cache::{Cache, CacheControl, CacheRule, Entry, Purge};
 #[cfg(feature = "cert_dir")]
 pub use cert_loader::CertDir;
 pub use cert_loader::{CertLoad, CertLoader};
+pub use compress::{Compress, Encoding};
+pub use concurrency::{Concurrency, Permit};
 pub use error::{Error, IntoError, Result};
+pub use header_rule::{HeaderOp, HeaderRule, HeaderSide};
+pub use health::Health;
+pub use hedge::Hedge;
+pub use host::normalize as normalize_host;
+pub use locale::{Locale, LocaleAction, LocaleBy, LocaleRoute};
+pub use mirror::{Mirror, MirrorStat};
+pub use park::Park;
+pub use peer::Peer;
 pub use proxy::proxy;
-pub use route::{Protocol, Route, SiteConf, Upstream};
+pub use rate_limit::{LimitBy, RateLimit};
+pub use redirect::{Jump, RedirectMap, RedirectTable};
+#[cfg(feature = "redis")]
+pub use redis_store::{RedisStore, Shared};
+pub use res_rewrite::ResRewrite;
+pub use retry::Retry;
+pub use route::{Canonical, H1, Protocol, Route, SiteConf, UpHost, Upstream};
+pub use rule::{PathMatch, Rule};
+pub use sec_header::SecHeader;
+pub use split::{Group, Pick, Split};
 pub use srv::srv;
+pub use sticky::Sticky;
+pub use tpl::{Tpl, Vars};
 
-pub fn req_host<B>(req: &hyper::Request<B>) -> &str {
-  req
-    .headers()
-    .get("host")
-    .map(|h| h.to_str().unwrap_or_default())
-    .unwrap_or_else(|| req.uri().host().unwrap_or_default())
+/// 请求的域名, 已规范化
+pub fn req_host<B>(req: &hyper::Request<B>) -> FastStr {
+  normalize_host(
+    req
+      .headers()
+      .get("host")
+      // 部分客户端会直接发送 utf8 的国际化域名
+      .map(|h| std::str::from_utf8(h.as_bytes()).unwrap_or_default())
+      .unwrap_or_else(|| req.uri().host().unwrap_or_default()),
+  )
 }
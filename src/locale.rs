@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+
+use faststr::FastStr;
+use http::{HeaderMap, Method, StatusCode, header::ACCEPT_LANGUAGE};
+
+use crate::cookie;
+
+/// 多语言内容的组织方式
+#[derive(Debug, Clone)]
+pub enum LocaleBy {
+  /// 语言作为路径前缀, 如 /en/ /zh/
+  Path,
+  /// 每种语言一个域名, 语言 -> 域名
+  Host(HashMap<FastStr, FastStr>),
+}
+
+/// 请求未带语言时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocaleAction {
+  Redirect(StatusCode),
+  /// 内部改写后转发, 不跳转
+  Rewrite,
+}
+
+/// 站点的语言策略
+#[derive(Debug, Clone)]
+pub struct Locale {
+  /// 站点支持的语言, 第一个为默认语言
+  pub lang_li: Box<[FastStr]>,
+  /// 可覆盖协商结果的 cookie 名
+  pub cookie: Option<FastStr>,
+  pub by: LocaleBy,
+  pub action: LocaleAction,
+  /// 不做语言处理的路径前缀, 如 /assets/
+  pub skip: Box<[FastStr]>,
+}
+
+/// 语言处理的结果
+#[derive(Debug, PartialEq, Eq)]
+pub enum LocaleRoute {
+  /// 请求已带语言
+  Has(FastStr),
+  Redirect {
+    lang: FastStr,
+    status: StatusCode,
+    location: String,
+  },
+  /// 改写后转发, host 为 None 时不改域名
+  Rewrite {
+    lang: FastStr,
+    host: Option<FastStr>,
+    path_and_query: String,
+  },
+}
+
+// 主语言标签, 如 zh-CN -> zh
+fn primary(tag: &str) -> &str {
+  tag.split(['-', '_']).next().unwrap_or(tag)
+}
+
+impl Locale {
+  pub fn new(lang_li: impl Into<Box<[FastStr]>>, by: LocaleBy, action: LocaleAction) -> Self {
+    Self {
+      lang_li: lang_li.into(),
+      cookie: None,
+      by,
+      action,
+      skip: Box::default(),
+    }
+  }
+
+  fn find(&self, tag: &str) -> Option<&FastStr> {
+    self.lang_li.iter().find(|l| l.eq_ignore_ascii_case(tag))
+  }
+
+  /// 路径或域名中已带的语言
+  pub fn lang_of(&self, host: &str, path: &str) -> Option<FastStr> {
+    match &self.by {
+      LocaleBy::Path => {
+        let seg = path.trim_start_matches('/').split('/').next()?;
+        self.lang_li.iter().find(|l| l.as_str() == seg).cloned()
+      }
+      LocaleBy::Host(host_map) => host_map
+        .iter()
+        .find_map(|(lang, h)| (h == host).then(|| lang.clone())),
+    }
+  }
+
+  /// 先看 cookie, 再按 Accept-Language 的权重协商, 都不匹配时用默认语言
+  pub fn negotiate(&self, headers: &HeaderMap) -> Option<FastStr> {
+    if let Some(name) = &self.cookie
+      && let Some(val) = cookie::get(headers, name)
+      && let Some(lang) = self.find(val)
+    {
+      return Some(lang.clone());
+    }
+
+    let mut tag_li: Vec<(f32, &str)> = headers
+      .get_all(ACCEPT_LANGUAGE)
+      .iter()
+      .filter_map(|v| v.to_str().ok())
+      .flat_map(|v| v.split(','))
+      .filter_map(|item| {
+        let mut it = item.split(';');
+        let tag = it.next()?.trim();
+        let q = it
+          .find_map(|p| p.trim().strip_prefix("q="))
+          .and_then(|q| q.parse().ok())
+          .unwrap_or(1.0);
+        (!tag.is_empty() && q > 0.0).then_some((q, tag))
+      })
+      .collect();
+    tag_li.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    for (_, tag) in tag_li {
+      if tag == "*" {
+        break;
+      }
+      if let Some(lang) = self.find(tag) {
+        return Some(lang.clone());
+      }
+      let tag = primary(tag);
+      if let Some(lang) = self
+        .lang_li
+        .iter()
+        .find(|l| primary(l).eq_ignore_ascii_case(tag))
+      {
+        return Some(lang.clone());
+      }
+    }
+    self.lang_li.first().cloned()
+  }
+
+  pub fn route(
+    &self,
+    method: &Method,
+    host: &str,
+    path_and_query: &str,
+    headers: &HeaderMap,
+  ) -> Option<LocaleRoute> {
+    let path = path_and_query
+      .split_once('?')
+      .map_or(path_and_query, |(p, _)| p);
+    if let Some(lang) = self.lang_of(host, path) {
+      return Some(LocaleRoute::Has(lang));
+    }
+    if !(method == Method::GET || method == Method::HEAD)
+      || self.skip.iter().any(|p| path.starts_with(p.as_str()))
+    {
+      return None;
+    }
+    let lang = self.negotiate(headers)?;
+    Some(match &self.by {
+      LocaleBy::Path => match self.action {
+        LocaleAction::Redirect(status) => LocaleRoute::Redirect {
+          location: format!("/{lang}{path_and_query}"),
+          lang,
+          status,
+        },
+        LocaleAction::Rewrite => LocaleRoute::Rewrite {
+          path_and_query: format!("/{lang}{path_and_query}"),
+          lang,
+          host: None,
+        },
+      },
+      LocaleBy::Host(host_map) => {
+        let lang_host = host_map.get(&lang)?.clone();
+        match self.action {
+          LocaleAction::Redirect(status) => LocaleRoute::Redirect {
+            location: format!("https://{lang_host}{path_and_query}"),
+            lang,
+            status,
+          },
+          LocaleAction::Rewrite => LocaleRoute::Rewrite {
+            path_and_query: path_and_query.into(),
+            lang,
+            host: Some(lang_host),
+          },
+        }
+      }
+    })
+  }
+
+  /// 响应的 Vary 头
+  pub fn vary(&self) -> &'static str {
+    if self.cookie.is_some() {
+      "Accept-Language, Cookie"
+    } else {
+      "Accept-Language"
+    }
+  }
+}
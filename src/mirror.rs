@@ -0,0 +1,104 @@
+use std::{
+  sync::{
+    Arc,
+    atomic::{AtomicU64, AtomicUsize, Ordering::Relaxed},
+  },
+  time::Duration,
+};
+
+use http::StatusCode;
+
+use crate::Upstream;
+
+/// 一组请求的状态码和延迟统计
+#[derive(Debug, Default)]
+pub struct MirrorStat {
+  count: AtomicU64,
+  error: AtomicU64,
+  // 按状态码的百位计数, 下标 1 到 5
+  status: [AtomicU64; 6],
+  // 延迟总和, 毫秒, 到收到响应头为止
+  latency_ms: AtomicU64,
+}
+
+impl MirrorStat {
+  /// 记录一次请求, status 为 None 表示出错
+  pub fn record(&self, status: Option<StatusCode>, latency: Duration) {
+    self.count.fetch_add(1, Relaxed);
+    self
+      .latency_ms
+      .fetch_add(latency.as_millis() as u64, Relaxed);
+    match status {
+      Some(status) => {
+        self.status[(status.as_u16() / 100).min(5) as usize].fetch_add(1, Relaxed);
+      }
+      None => {
+        self.error.fetch_add(1, Relaxed);
+      }
+    }
+  }
+
+  pub fn count(&self) -> u64 {
+    self.count.load(Relaxed)
+  }
+
+  pub fn error(&self) -> u64 {
+    self.error.load(Relaxed)
+  }
+
+  /// 状态码百位为 class 的请求数, 如 class 为 5 时是 5xx 的数量
+  pub fn status(&self, class: u16) -> u64 {
+    self
+      .status
+      .get(class as usize)
+      .map_or(0, |n| n.load(Relaxed))
+  }
+
+  /// 平均延迟, 毫秒
+  pub fn avg_latency_ms(&self) -> u64 {
+    self.latency_ms.load(Relaxed) / self.count().max(1)
+  }
+}
+
+/// 镜像流量: 按比例把请求复制一份发给影子上游, 不等待也不返回它的响应
+/// 分别记录被镜像请求在主上游和影子上游的状态码和延迟, 用于对比新版本
+#[derive(Debug)]
+pub struct Mirror {
+  pub upstream: Arc<Upstream>,
+  // 镜像的比例, 0.0 到 1.0
+  pub ratio: f64,
+  // 同时进行的镜像请求上限, 超过时不再镜像, 避免影子上游拖累网关
+  pub max_in_flight: usize,
+  pub primary: MirrorStat,
+  pub shadow: MirrorStat,
+  in_flight: AtomicUsize,
+}
+
+impl Mirror {
+  pub fn new(upstream: Arc<Upstream>, ratio: f64) -> Self {
+    Self {
+      upstream,
+      ratio,
+      max_in_flight: 256,
+      primary: MirrorStat::default(),
+      shadow: MirrorStat::default(),
+      in_flight: AtomicUsize::new(0),
+    }
+  }
+
+  /// 按比例抽样, 选中时占用一个进行中的名额, 需要调用 done 归还
+  pub fn pick(&self) -> bool {
+    self.ratio > 0.0
+      && fastrand::f64() < self.ratio
+      && self
+        .in_flight
+        .fetch_update(Relaxed, Relaxed, |n| {
+          (n < self.max_in_flight).then_some(n + 1)
+        })
+        .is_ok()
+  }
+
+  pub fn done(&self) {
+    self.in_flight.fetch_sub(1, Relaxed);
+  }
+}
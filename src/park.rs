@@ -0,0 +1,72 @@
+use std::{
+  net::SocketAddr,
+  sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering::SeqCst},
+  },
+  time::Duration,
+};
+
+use tokio::{net::TcpStream, sync::watch, time::Instant};
+
+/// 上游整组不可用时暂存请求 (如后端正在重启), 探测到有地址可连接时唤醒重试
+/// 同一组上游只有一个探测任务, 没有等待的请求时停止
+#[derive(Debug)]
+pub struct Park {
+  // 请求最多等待多久
+  pub timeout: Duration,
+  // 两轮探测的间隔
+  pub interval: Duration,
+  // 单次 TCP 连接的超时
+  pub connect_timeout: Duration,
+  probing: AtomicBool,
+  // 最近探测到可连接的地址下标
+  up: watch::Sender<usize>,
+}
+
+impl Park {
+  pub fn new(timeout: Duration) -> Self {
+    Self {
+      timeout,
+      interval: Duration::from_millis(200),
+      connect_timeout: Duration::from_millis(500),
+      probing: AtomicBool::new(false),
+      up: watch::Sender::new(0),
+    }
+  }
+
+  /// 等到有地址可连接, 返回其下标, 超过 deadline 返回 None
+  pub async fn wait(self: &Arc<Self>, addr_li: &[SocketAddr], deadline: Instant) -> Option<usize> {
+    // 先订阅再启动探测, 不会错过通知
+    let mut rx = self.up.subscribe();
+    if !self.probing.swap(true, SeqCst) {
+      tokio::spawn(self.clone().probe(addr_li.into()));
+    }
+    match tokio::time::timeout_at(deadline, rx.changed()).await {
+      Ok(Ok(())) => Some(*rx.borrow_and_update()),
+      _ => None,
+    }
+  }
+
+  async fn probe(self: Arc<Self>, addr_li: Box<[SocketAddr]>) {
+    loop {
+      for (pos, addr) in addr_li.iter().enumerate() {
+        if let Ok(Ok(_)) =
+          tokio::time::timeout(self.connect_timeout, TcpStream::connect(addr)).await
+        {
+          self.probing.store(false, SeqCst);
+          self.up.send_replace(pos);
+          return;
+        }
+      }
+      if self.up.receiver_count() == 0 {
+        self.probing.store(false, SeqCst);
+        // 停止前又有请求开始等待, 且没有别的探测任务时继续
+        if self.up.receiver_count() == 0 || self.probing.swap(true, SeqCst) {
+          return;
+        }
+      }
+      tokio::time::sleep(self.interval).await;
+    }
+  }
+}
@@ -0,0 +1,15 @@
+use std::net::SocketAddr;
+
+/// 客户端连接信息
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Peer {
+  pub addr: SocketAddr,
+  // 是否经过 TLS (h2, h3)
+  pub tls: bool,
+}
+
+impl Peer {
+  pub fn new(addr: SocketAddr, tls: bool) -> Self {
+    Self { addr, tls }
+  }
+}
@@ -1,45 +1,73 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
-use http::{Request, Response, StatusCode, header, response::Builder};
+use faststr::FastStr;
+use http::{
+  HeaderValue, Method, Request, Response, StatusCode, Uri, header, request::Parts,
+  response::Builder,
+};
 use http_body::Body;
 use http_body_util::{BodyExt, Full, combinators::BoxBody};
 use hyper::body::Bytes;
-use sub_host::sub_host;
 
-use crate::{Error, IntoError, Result, Route, req_host, route::Protocol::H1};
+use crate::{
+  Error, HeaderSide, Hedge, IntoError, LocaleRoute, Mirror, Peer, Result, Route, SiteConf, UpHost,
+  Upstream, Vars, cache, header_rule, req_host, route::Protocol::H1, rule,
+};
 
 pub static mut N: usize = 0;
 
-pub async fn proxy<B>(req: Request<B>, route: Arc<Route>) -> Response<BoxBody<Bytes, hyper::Error>>
+pub async fn proxy<B>(req: Request<B>, route: Arc<Route>) -> Response<BoxBody<Bytes, Error>>
 where
   B: Body<Data = Bytes> + Send + 'static,
   B::Error: IntoError + Send + Sync + 'static,
 {
-  let host = req_host(&req).to_owned();
+  let host = req_host(&req);
+  if let Some(admin) = &route.admin
+    && admin.host == host
+  {
+    return admin
+      .serve(&req, &route)
+      .unwrap_or_else(|err| response(|b| b.status(500), err.to_string()).unwrap_or_default());
+  }
   let path = req
     .uri()
     .path_and_query()
     .map(|x| x.as_str())
     .unwrap_or("")
     .to_owned();
-  match _proxy(&host, &path, req, route).await {
+  let site = route.site(&host);
+  let sec_header = match &site {
+    Some((conf, _)) => conf.sec_header.clone(),
+    // 跳转到规范域名时用目标站点的配置
+    None => route.canonical(&host).and_then(|(_, conf)| conf.sec_header),
+  };
+  let site_h3 = site.as_ref().is_none_or(|(conf, _)| conf.h3);
+  let tls = req.extensions().get::<Peer>().is_some_and(|peer| peer.tls);
+  let version = req.version();
+  let mut res = match _proxy(&host, &path, req, route.clone(), site).await {
     Ok(res) => {
       let status = res.status();
       log::info!("{status} {host} {path}");
       res
     }
     Err(err) => {
+      let status = err.status();
       let err = err.to_string();
       log::warn!("Error: {host} {path} {err}");
-      response(|b| b.status(500), err).unwrap_or_default()
+      response(|b| b.status(status), err).unwrap_or_default()
     }
+  };
+  if let Some(sec_header) = sec_header {
+    sec_header.apply(res.headers_mut(), tls);
   }
+  route.alt_svc.apply(res.headers_mut(), version, site_h3);
+  res
 }
 
 fn response(
   build: impl Fn(Builder) -> Builder,
   body: impl Into<Bytes>,
-) -> Result<Response<BoxBody<Bytes, hyper::Error>>> {
+) -> Result<Response<BoxBody<Bytes, Error>>> {
   Ok(
     build(Builder::new()).body(
       Full::new(body.into())
@@ -49,75 +77,441 @@ fn response(
   )
 }
 
+// 替换 uri 的路径和查询参数, 保留 scheme 和 authority
+pub fn set_path_and_query(uri: &Uri, path_and_query: &str) -> Result<Uri> {
+  let mut parts = uri.clone().into_parts();
+  parts.path_and_query = Some(path_and_query.parse()?);
+  Ok(Uri::from_parts(parts)?)
+}
+
+// 缓存键: 规范化的域名加转发给上游的路径
+fn cache_key(host: &str, parts: &Parts) -> FastStr {
+  let path_and_query = parts.uri.path_and_query().map_or("/", |pq| pq.as_str());
+  format!("{host}{path_and_query}").into()
+}
+
 pub async fn _proxy<B>(
   host: &str,
   path_and_query: &str,
   req: Request<B>,
   route: Arc<Route>,
-) -> Result<Response<BoxBody<Bytes, hyper::Error>>>
+  site: Option<(SiteConf, Box<[FastStr]>)>,
+) -> Result<Response<BoxBody<Bytes, Error>>>
 where
   B: Body<Data = Bytes> + Send + 'static,
   B::Error: IntoError + Send + Sync + 'static,
 {
-  if let Some(site_conf) = route.host_conf.get(host) {
-    let site_conf = site_conf.value();
-    let upstream = &site_conf.upstream;
-    let protocol = &upstream.protocol;
-    let upstream_addr_li = &upstream.addr_li;
-    let len = upstream_addr_li.len();
-    if len == 0 {
-      return Err(Error::UpstreamNotFound);
+  // site 为克隆出的配置, 避免跨 await 持有 DashMap 的锁
+  let Some((site_conf, capture)) = site else {
+    if let Some((site, conf)) = route.canonical(host) {
+      return response(
+        |b| {
+          let new_uri = format!("https://{site}{path_and_query}");
+          b.status(conf.canonical_status)
+            .header(header::LOCATION, new_uri)
+        },
+        &b""[..],
+      );
     }
-    let (mut parts, body) = req.into_parts();
-
-    match protocol {
-      H1 => {
-        parts.version = http::Version::HTTP_11;
-        parts.headers.insert(
-          header::CONNECTION,
-          header::HeaderValue::from_static("keep-alive"),
+    return response(|b| b.status(404), &b"404: Not Found"[..]);
+  };
+
+  let (mut parts, body) = req.into_parts();
+  if let Some(retry_after) = site_conf
+    .limited(host, parts.uri.path(), &parts.headers, &parts.extensions)
+    .await
+  {
+    return response(
+      |b| {
+        b.status(StatusCode::TOO_MANY_REQUESTS)
+          .header(header::RETRY_AFTER, retry_after)
+      },
+      &b"429: Too Many Requests"[..],
+    );
+  }
+
+  if let Some(redirect) = &site_conf.redirect
+    && let Some((status, location)) = redirect.get(path_and_query)
+  {
+    return response(
+      |b| b.status(status).header(header::LOCATION, &location),
+      &b""[..],
+    );
+  }
+
+  // 由监听端写入请求扩展
+  let client_ip = parts
+    .extensions
+    .get::<Peer>()
+    .map(|peer| peer.addr.ip().to_string())
+    .unwrap_or_default();
+  let request_id = header_rule::request_id();
+
+  let locale = site_conf.locale.as_deref();
+  let mut lang = None;
+  let mut vary = None;
+  let mut lang_prefix = None;
+  // 语言改写了域名时, 缓存键用改写后的域名
+  let mut cache_host = None;
+  if let Some(locale) = locale {
+    match locale.route(&parts.method, host, path_and_query, &parts.headers) {
+      Some(LocaleRoute::Redirect {
+        status, location, ..
+      }) => {
+        return response(
+          |b| {
+            b.status(status)
+              .header(header::LOCATION, &location)
+              .header(header::VARY, locale.vary())
+          },
+          &b""[..],
         );
       }
+      Some(LocaleRoute::Rewrite {
+        lang: l,
+        host: lang_host,
+        path_and_query,
+      }) => {
+        parts.uri = set_path_and_query(&parts.uri, &path_and_query)?;
+        match lang_host {
+          Some(lang_host) => {
+            parts
+              .headers
+              .insert(header::HOST, HeaderValue::from_str(&lang_host)?);
+            cache_host = Some(lang_host);
+          }
+          None => lang_prefix = Some(format!("/{l}")),
+        }
+        lang = Some(l);
+        vary = Some(locale.vary());
+      }
+      Some(LocaleRoute::Has(l)) => lang = Some(l),
+      None => {}
     }
-    let body = body.collect().await.map_err(|e| e.into_error())?.to_bytes();
-    let mut pos = unsafe {
-      N = N.overflowing_add(1).0;
-      N
-    } % len;
-    let mut retry = 0;
-    loop {
-      let upstream_addr = upstream_addr_li[pos];
-      let req = Request::from_parts(parts.clone(), Full::new(body.clone()));
-      let r = match protocol {
-        H1 => pooled_fetch::http(upstream_addr, req).await,
+  }
+
+  let mut upstream = &site_conf.upstream;
+  let mut set_cookie = None;
+  let mut hedge = None;
+  // 规则按客户端请求的路径匹配和改写, 不受语言改写影响, 改写后再补回语言前缀
+  let path = path_and_query
+    .split_once('?')
+    .map_or(path_and_query, |(path, _)| path);
+  if let Some(rule) = rule::find(&site_conf.rule_li, &parts.method, path, &parts.headers) {
+    upstream = &rule.upstream;
+    hedge = rule.hedge.clone();
+    if let Some(rewrite) = rule.rewrite(path_and_query) {
+      let rewrite = match &lang_prefix {
+        Some(prefix) => format!("{prefix}{rewrite}"),
+        None => rewrite,
       };
-      match r {
-        Ok(res) => {
-          return Ok(res.map(|b| b.boxed()));
+      parts.uri = set_path_and_query(&parts.uri, &rewrite)?;
+    }
+  } else if let Some(split) = &site_conf.split
+    && let Some(pick) = split.pick(&parts.headers)
+  {
+    upstream = &pick.group.upstream;
+    set_cookie = pick.set_cookie;
+  }
+
+  if let Some(tpl) = &site_conf.path_tpl {
+    let vars = Vars {
+      capture: &capture,
+      host,
+      path_and_query: parts.uri.path_and_query().map_or("/", |pq| pq.as_str()),
+      client_ip: &client_ip,
+      request_id: &request_id,
+    };
+    parts.uri = set_path_and_query(&parts.uri, &tpl.render(&vars))?;
+  }
+
+  // 头规则按客户端请求的路径匹配
+  let vars = Vars {
+    capture: &capture,
+    host,
+    path_and_query,
+    client_ip: &client_ip,
+    request_id: &request_id,
+  };
+  header_rule::apply(
+    &site_conf.header_rule_li,
+    HeaderSide::Req,
+    &mut parts.headers,
+    &vars,
+  )?;
+
+  // HEAD 没有响应体, 不压缩
+  let accept_encoding = (parts.method != Method::HEAD)
+    .then(|| parts.headers.get(header::ACCEPT_ENCODING).cloned())
+    .flatten();
+  let body = body.collect().await.map_err(|e| e.into_error())?.to_bytes();
+  // 缓存键不含上游, 规则或分流选了其他上游时不缓存, 避免不同上游的响应互相覆盖
+  let mut res = if let Some(rule) = &site_conf.cache
+    && (parts.method == Method::GET || parts.method == Method::HEAD)
+    && Arc::ptr_eq(upstream, &site_conf.upstream)
+  {
+    let key = cache_key(cache_host.as_deref().unwrap_or(host), &parts);
+    // 后台刷新时在请求结束后调用, 需要持有数据
+    let (host, path_and_query, upstream, mirror) = (
+      host.to_owned(),
+      path_and_query.to_owned(),
+      upstream.clone(),
+      site_conf.mirror.clone(),
+    );
+    cache::fetch(&route.cache, rule, key, parts, move |parts| async move {
+      mirror_fetch(
+        mirror.as_ref(),
+        &host,
+        &path_and_query,
+        &upstream,
+        hedge.as_deref(),
+        parts,
+        body,
+      )
+      .await
+    })
+    .await?
+  } else {
+    mirror_fetch(
+      site_conf.mirror.as_ref(),
+      host,
+      path_and_query,
+      upstream,
+      hedge.as_deref(),
+      parts,
+      body,
+    )
+    .await?
+  };
+
+  if let Some(compress) = &site_conf.compress {
+    res = compress.apply(accept_encoding.as_ref(), res);
+  }
+
+  let headers = res.headers_mut();
+  if let Some(set_cookie) = set_cookie {
+    headers.append(header::SET_COOKIE, HeaderValue::from_str(&set_cookie)?);
+  }
+  if let Some(vary) = vary {
+    headers.append(header::VARY, HeaderValue::from_static(vary));
+  }
+  if let Some(lang) = lang
+    && !headers.contains_key(header::CONTENT_LANGUAGE)
+  {
+    headers.insert(header::CONTENT_LANGUAGE, HeaderValue::from_str(&lang)?);
+  }
+  if let Some(res_rewrite) = &site_conf.res_rewrite {
+    res_rewrite.apply(headers, &vars)?;
+  }
+  header_rule::apply(&site_conf.header_rule_li, HeaderSide::Res, headers, &vars)?;
+  Ok(res)
+}
+
+// 请求上游, 按比例把请求镜像给影子上游, 并记录被镜像请求在主上游的状态码和延迟
+// 只包住真正发往上游的请求, 命中缓存的请求不镜像, 也不计入主上游的统计
+async fn mirror_fetch(
+  mirror: Option<&Arc<Mirror>>,
+  host: &str,
+  path_and_query: &str,
+  upstream: &Upstream,
+  hedge: Option<&Hedge>,
+  parts: Parts,
+  body: Bytes,
+) -> Result<Response<BoxBody<Bytes, Error>>> {
+  let Some(mirror) = mirror.filter(|mirror| mirror.pick()) else {
+    return fetch(host, path_and_query, upstream, hedge, parts, body).await;
+  };
+  shadow(
+    mirror.clone(),
+    host.to_owned(),
+    path_and_query.to_owned(),
+    parts.clone(),
+    body.clone(),
+  );
+  let start = Instant::now();
+  let res = fetch(host, path_and_query, upstream, hedge, parts, body).await;
+  mirror
+    .primary
+    .record(res.as_ref().ok().map(|res| res.status()), start.elapsed());
+  res
+}
+
+// 把请求复制给影子上游, 丢弃响应, 只记录状态码和延迟
+fn shadow(mirror: Arc<Mirror>, host: String, path_and_query: String, parts: Parts, body: Bytes) {
+  tokio::spawn(async move {
+    let start = Instant::now();
+    let r = fetch(&host, &path_and_query, &mirror.upstream, None, parts, body).await;
+    let latency = start.elapsed();
+    mirror
+      .shadow
+      .record(r.as_ref().ok().map(|res| res.status()), latency);
+    match r {
+      Ok(res) => {
+        log::info!(
+          "mirror {} {host} {path_and_query} {}ms",
+          res.status(),
+          latency.as_millis()
+        );
+        // 读完响应体, 连接才能复用
+        let _ = res.into_body().collect().await;
+      }
+      Err(err) => log::warn!("mirror {host} {path_and_query} {err}"),
+    }
+    mirror.done();
+  });
+}
+
+async fn fetch(
+  host: &str,
+  path_and_query: &str,
+  upstream: &Upstream,
+  hedge: Option<&Hedge>,
+  mut parts: Parts,
+  body: Bytes,
+) -> Result<Response<BoxBody<Bytes, Error>>> {
+  let protocol = &upstream.protocol;
+  let upstream_addr_li = &upstream.addr_li;
+  let len = upstream_addr_li.len();
+  if len == 0 {
+    return Err(Error::UpstreamNotFound);
+  }
+
+  // h2, h3 请求的域名在 :authority 中, 转为 Host 头
+  if let UpHost::Set(up_host) = &upstream.host {
+    parts
+      .headers
+      .insert(header::HOST, HeaderValue::from_str(up_host)?);
+  } else if !parts.headers.contains_key(header::HOST)
+    && let Some(authority) = parts.uri.authority()
+  {
+    parts
+      .headers
+      .insert(header::HOST, HeaderValue::from_str(authority.as_str())?);
+  }
+
+  match protocol {
+    H1 => {
+      parts.version = http::Version::HTTP_11;
+      // 降级到 h1 时用 origin-form, 只保留路径和查询参数
+      if parts.uri.authority().is_some() {
+        let path_and_query = parts.uri.path_and_query().map_or("/", |pq| pq.as_str());
+        parts.uri = Uri::builder().path_and_query(path_and_query).build()?;
+      }
+      parts.headers.insert(
+        header::CONNECTION,
+        header::HeaderValue::from_static("keep-alive"),
+      );
+    }
+  }
+  // 优先使用会话保持的地址, 不可用时回退到轮询
+  let sticky_pos = upstream.sticky.as_ref().and_then(|sticky| {
+    sticky
+      .find(&parts.headers, upstream_addr_li)
+      .filter(|&pos| upstream.health.is_up(upstream_addr_li[pos]))
+  });
+  let mut pos = match sticky_pos {
+    Some(pos) => pos,
+    None => upstream.up_pos(
+      unsafe {
+        N = N.overflowing_add(1).0;
+        N
+      } % len,
+    ),
+  };
+  upstream.retry.request();
+  let mut retry = 0;
+  let mut park_deadline = None;
+  let hedge = hedge.filter(|hedge| len > 1 && hedge.is_hedgeable(&parts.method));
+  loop {
+    // 超出并发时排队, 队列满或超时返回 503
+    // 每次请求单独取许可, 重试之间不占用
+    let permit = match &upstream.concurrency {
+      Some(concurrency) => Some(concurrency.acquire().await?),
+      None => None,
+    };
+    let send = |pos: usize| {
+      let req = Request::from_parts(parts.clone(), Full::new(body.clone()));
+      match protocol {
+        H1 => pooled_fetch::http(upstream_addr_li[pos], req),
+      }
+    };
+    // 只对冲第一次请求, 重试时不再对冲
+    let r = match hedge {
+      Some(hedge) if retry == 0 => {
+        let next = upstream.up_pos((pos + 1) % len);
+        let (p, r) = hedge.race(pos, next, send).await;
+        pos = p;
+        r
+      }
+      _ => send(pos).await,
+    };
+    let upstream_addr = upstream_addr_li[pos];
+    match r {
+      Ok(res) => {
+        upstream.health.ok(upstream_addr);
+        if let Some(permit) = permit {
+          permit.done(!res.status().is_server_error());
         }
-        Err(err) => {
-          log::warn!("Error: {host} {path_and_query} {upstream_addr} {}", err);
+        if retry < upstream.max_retry
+          && upstream.retry.on_status(&parts.method, res.status())
+          && upstream.retry.budget()
+        {
+          log::warn!(
+            "Retry: {host} {path_and_query} {upstream_addr} {}",
+            res.status()
+          );
+          drop(res);
           retry += 1;
-          if retry > upstream.max_retry {
-            return Err(err.into());
+          tokio::time::sleep(upstream.retry.backoff(retry)).await;
+          pos = upstream.up_pos((pos + 1) % len);
+          continue;
+        }
+        let mut res = res.map(|b| b.map_err(Error::from).boxed());
+        if let Some(sticky) = &upstream.sticky
+          && sticky_pos != Some(pos)
+        {
+          res.headers_mut().append(
+            header::SET_COOKIE,
+            HeaderValue::from_str(&sticky.set_cookie(upstream_addr))?,
+          );
+        }
+        return Ok(res);
+      }
+      Err(err) => {
+        if let Some(permit) = permit {
+          permit.done(false);
+        }
+        log::warn!("Error: {host} {path_and_query} {upstream_addr} {}", err);
+        upstream
+          .health
+          .fail(upstream_addr, upstream.fail_timeout_sec);
+        retry += 1;
+        // 已发出的非幂等请求不重试, 以免上游重复处理
+        let retryable = upstream.retry.on_error(&parts.method, err.is_connect());
+        // 整组都不可用时等待恢复, 恢复后重新计算重试次数
+        // 等待恢复不是重试, 不受重试次数和预算限制
+        if retryable
+          && let Some(park) = &upstream.park
+          && upstream_addr_li
+            .iter()
+            .all(|addr| !upstream.health.is_up(*addr))
+        {
+          let deadline =
+            *park_deadline.get_or_insert_with(|| tokio::time::Instant::now() + park.timeout);
+          if let Some(up) = park.wait(upstream_addr_li, deadline).await {
+            log::info!("unpark: {host} {path_and_query} {}", upstream_addr_li[up]);
+            upstream.health.ok(upstream_addr_li[up]);
+            pos = up;
+            retry = 0;
+            continue;
           }
-          pos = (pos + 1) % len;
         }
+        if !retryable || retry > upstream.max_retry || !upstream.retry.budget() {
+          return Err(err.into());
+        }
+        tokio::time::sleep(upstream.retry.backoff(retry)).await;
+        pos = upstream.up_pos((pos + 1) % len);
       }
     }
-  } else {
-    if let Some(host) = sub_host(host)
-      && route.host_conf.get(&faststr::FastStr::new(&host)).is_some()
-    {
-      return response(
-        |b| {
-          let new_uri = format!("https://{}{}", host, path_and_query);
-          b.status(StatusCode::MOVED_PERMANENTLY)
-            .header(header::LOCATION, new_uri)
-        },
-        &b""[..],
-      );
-    }
-    response(|b| b.status(404), &b"404: Not Found"[..])
   }
 }
@@ -0,0 +1,146 @@
+use std::{
+  net::IpAddr,
+  sync::atomic::{AtomicU64, Ordering::Relaxed},
+};
+
+use coarsetime::Clock;
+use dashmap::DashMap;
+use faststr::FastStr;
+use http::{HeaderMap, HeaderName};
+
+use crate::PathMatch;
+#[cfg(feature = "redis")]
+use crate::Shared;
+
+// 桶的数量超过该值时清理已经回满的桶
+const GC_LEN: usize = 4096;
+
+/// 限流的维度
+#[derive(Debug, Clone)]
+pub enum LimitBy {
+  /// 客户端 IP
+  Ip,
+  /// 站点域名, 所有客户端共用一个桶
+  Host,
+  /// 请求头的值, 如 API key, 没有该请求头时按客户端 IP
+  Header(HeaderName),
+}
+
+#[derive(Debug)]
+struct Bucket {
+  token: f64,
+  // 上次更新的时间, 毫秒
+  time: u64,
+}
+
+/// 令牌桶限流, 每个键一个桶
+#[derive(Debug)]
+pub struct RateLimit {
+  pub by: LimitBy,
+  // 只统计路径匹配的请求
+  pub path: PathMatch,
+  // 每秒补充的令牌数
+  pub rate: f64,
+  // 桶的容量, 即允许的突发请求数
+  pub burst: f64,
+  bucket: DashMap<FastStr, Bucket>,
+  n: AtomicU64,
+  // 多个实例共用的计数, Redis 不可用时回退到本地计数
+  #[cfg(feature = "redis")]
+  pub shared: Option<Shared>,
+}
+
+impl RateLimit {
+  pub fn new(by: LimitBy, rate: f64, burst: u32) -> Self {
+    Self {
+      by,
+      path: PathMatch::Any,
+      rate,
+      burst: burst.max(1) as f64,
+      bucket: DashMap::new(),
+      n: AtomicU64::new(0),
+      #[cfg(feature = "redis")]
+      shared: None,
+    }
+  }
+
+  /// 限流的键, 路径不匹配时返回 None
+  pub fn key(&self, host: &str, path: &str, headers: &HeaderMap, ip: IpAddr) -> Option<FastStr> {
+    if !self.path.is_match(path) {
+      return None;
+    }
+    Some(match &self.by {
+      LimitBy::Ip => ip.to_string().into(),
+      LimitBy::Host => FastStr::new(host),
+      LimitBy::Header(name) => match headers.get(name) {
+        Some(v) => format!("{name}:{}", String::from_utf8_lossy(v.as_bytes())).into(),
+        None => ip.to_string().into(),
+      },
+    })
+  }
+
+  /// 取一个令牌, 有共用计数时优先使用, 不足时返回需要等待的秒数
+  pub async fn acquire(&self, key: FastStr) -> Result<(), u64> {
+    #[cfg(feature = "redis")]
+    if let Some(shared) = &self.shared
+      && !shared.store.is_down()
+    {
+      let redis_key = format!("{}:{key}", shared.prefix);
+      match shared.store.take(&redis_key, self.rate, self.burst).await {
+        Ok(0) => return Ok(()),
+        Ok(ms) => return Err(ms.div_ceil(1000)),
+        Err(err) => log::warn!("rate limit redis: {err}"),
+      }
+    }
+    self.take(key)
+  }
+
+  /// 用本地计数取一个令牌, 不足时返回需要等待的秒数
+  pub fn take(&self, key: FastStr) -> Result<(), u64> {
+    let now = Clock::now_since_epoch().as_millis();
+    if self.n.fetch_add(1, Relaxed).is_multiple_of(1024) && self.bucket.len() > GC_LEN {
+      self.gc(now);
+    }
+    let mut bucket = self.bucket.entry(key).or_insert(Bucket {
+      token: self.burst,
+      time: now,
+    });
+    let elapsed = now.saturating_sub(bucket.time) as f64 / 1000.0;
+    bucket.token = (bucket.token + elapsed * self.rate).min(self.burst);
+    bucket.time = now;
+    if bucket.token >= 1.0 {
+      bucket.token -= 1.0;
+      return Ok(());
+    }
+    if self.rate <= 0.0 {
+      return Err(60);
+    }
+    Err(((1.0 - bucket.token) / self.rate).ceil().max(1.0) as u64)
+  }
+
+  // 删除已经回满的桶, 和新建的桶等价
+  fn gc(&self, now: u64) {
+    let full_ms = (self.burst / self.rate.max(f64::MIN_POSITIVE) * 1000.0) as u64;
+    self
+      .bucket
+      .retain(|_, b| now.saturating_sub(b.time) < full_ms);
+  }
+}
+
+/// 依次检查站点的限流规则, 被限流时返回需要等待的秒数
+pub async fn check(
+  rate_limit_li: &[RateLimit],
+  host: &str,
+  path: &str,
+  headers: &HeaderMap,
+  ip: IpAddr,
+) -> Option<u64> {
+  for limit in rate_limit_li {
+    if let Some(key) = limit.key(host, path, headers, ip)
+      && let Err(retry_after) = limit.acquire(key).await
+    {
+      return Some(retry_after);
+    }
+  }
+  None
+}
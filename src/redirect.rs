@@ -0,0 +1,154 @@
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+use faststr::FastStr;
+use http::StatusCode;
+use parking_lot::RwLock;
+
+use crate::{Error, Result};
+
+/// 单条跳转规则
+#[derive(Debug, Clone)]
+pub struct Jump {
+  pub to: FastStr,
+  pub status: StatusCode,
+  // 是否保留原请求的查询参数
+  pub keep_query: bool,
+}
+
+/// 跳转表, 精确路径和前缀规则分开存放
+#[derive(Debug, Default)]
+pub struct RedirectTable {
+  pub exact: HashMap<FastStr, Jump>,
+  // 键以 / 结尾, 如 /old/
+  pub prefix: HashMap<FastStr, Jump>,
+}
+
+impl RedirectTable {
+  /// 每行格式: 源路径 目标 [状态码] [keep|drop], # 开头为注释
+  /// 源路径以 /* 结尾为前缀规则, 目标以 * 结尾时把剩余路径拼接到目标后面
+  pub fn parse(txt: &str) -> Result<Self> {
+    let mut table = Self::default();
+    for (n, line) in txt.lines().enumerate() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let err = |msg: &str| Error::RedirectParse(format!("line {}: {msg}: {line}", n + 1));
+      let mut it = line.split_whitespace();
+      let (Some(from), Some(to)) = (it.next(), it.next()) else {
+        return Err(err("缺少目标"));
+      };
+      if !from.starts_with('/') {
+        return Err(err("源路径需以 / 开头"));
+      }
+      let mut jump = Jump {
+        to: FastStr::new(to),
+        status: StatusCode::MOVED_PERMANENTLY,
+        keep_query: true,
+      };
+      for opt in it {
+        match opt {
+          "keep" => jump.keep_query = true,
+          "drop" => jump.keep_query = false,
+          _ => {
+            jump.status = match opt.parse::<u16>() {
+              Ok(code @ (301 | 302 | 307 | 308)) => {
+                StatusCode::from_u16(code).map_err(|_| err("状态码无效"))?
+              }
+              _ => return Err(err("未知选项")),
+            };
+          }
+        }
+      }
+      if let Some(prefix) = from.strip_suffix('*') {
+        if !prefix.ends_with('/') {
+          return Err(err("前缀规则需以 /* 结尾"));
+        }
+        table.prefix.insert(FastStr::new(prefix), jump);
+      } else {
+        table.exact.insert(FastStr::new(from), jump);
+      }
+    }
+    Ok(table)
+  }
+
+  pub fn len(&self) -> usize {
+    self.exact.len() + self.prefix.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// 查找跳转, 返回状态码和 Location
+  pub fn get(&self, path_and_query: &str) -> Option<(StatusCode, String)> {
+    let (path, query) = path_and_query
+      .split_once('?')
+      .unwrap_or((path_and_query, ""));
+
+    let (jump, rest) = if let Some(jump) = self.exact.get(path) {
+      (jump, "")
+    } else {
+      // 从最长的前缀开始匹配, 每个 / 都是一个候选
+      path.rmatch_indices('/').find_map(|(pos, _)| {
+        self
+          .prefix
+          .get(&path[..=pos])
+          .map(|jump| (jump, &path[pos + 1..]))
+      })?
+    };
+
+    let mut location = match jump.to.strip_suffix('*') {
+      Some(base) => format!("{base}{rest}"),
+      None => jump.to.to_string(),
+    };
+    if jump.keep_query && !query.is_empty() {
+      location.push(if location.contains('?') { '&' } else { '?' });
+      location.push_str(query);
+    }
+    Some((jump.status, location))
+  }
+}
+
+/// 从文件加载的跳转表, 可在运行时重新加载
+#[derive(Debug)]
+pub struct RedirectMap {
+  pub file: PathBuf,
+  table: RwLock<Arc<RedirectTable>>,
+}
+
+impl RedirectMap {
+  pub async fn load(file: impl Into<PathBuf>) -> Result<Arc<Self>> {
+    let file = file.into();
+    let table = Self::read(&file).await?;
+    Ok(Arc::new(Self {
+      file,
+      table: RwLock::new(Arc::new(table)),
+    }))
+  }
+
+  async fn read(file: &PathBuf) -> Result<RedirectTable> {
+    let txt = tokio::fs::read_to_string(file).await?;
+    RedirectTable::parse(&txt).map_err(|e| match e {
+      Error::RedirectParse(msg) => Error::RedirectParse(format!("{}: {msg}", file.display())),
+      e => e,
+    })
+  }
+
+  /// 重新读取文件, 解析失败时保留旧的跳转表
+  pub async fn reload(&self) -> Result<usize> {
+    let table = Self::read(&self.file).await?;
+    let len = table.len();
+    *self.table.write() = Arc::new(table);
+    log::info!("重新加载跳转表 {} : {len} 条", self.file.display());
+    Ok(len)
+  }
+
+  pub fn table(&self) -> Arc<RedirectTable> {
+    self.table.read().clone()
+  }
+
+  pub fn get(&self, path_and_query: &str) -> Option<(StatusCode, String)> {
+    self.table.read().get(path_and_query)
+  }
+}
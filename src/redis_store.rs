@@ -0,0 +1,113 @@
+use std::{
+  sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering::Relaxed},
+  },
+  time::Duration,
+};
+
+use coarsetime::Clock;
+use faststr::FastStr;
+use parking_lot::Mutex;
+use redis::{Client, Script, aio::MultiplexedConnection};
+
+use crate::{Error, Result};
+
+// GCRA: 键中存理论到达时间 (毫秒), 用 Redis 的时钟, 各实例不必对时
+// 返回 0 表示放行, 否则为需要等待的毫秒数
+const GCRA: &str = r"
+local t = redis.call('TIME')
+local now = t[1] * 1000 + math.floor(t[2] / 1000)
+local emission = tonumber(ARGV[1])
+local burst = tonumber(ARGV[2])
+local tat = tonumber(redis.call('GET', KEYS[1]) or now)
+if tat < now then
+  tat = now
+end
+local new_tat = tat + emission
+local wait = new_tat - emission * burst - now
+if wait > 0 then
+  return math.ceil(wait)
+end
+redis.call('SET', KEYS[1], new_tat, 'PX', math.ceil(new_tat - now) + 1)
+return 0
+";
+
+/// 多个实例共用的限流计数, 存在 Redis 中
+#[derive(Debug)]
+pub struct RedisStore {
+  client: Client,
+  conn: Mutex<Option<MultiplexedConnection>>,
+  script: Script,
+  // 单次请求 Redis 的超时, 超时按出错处理
+  pub timeout: Duration,
+  // 出错后的这段时间内不再请求 Redis, 直接用本地计数
+  pub retry_sec: u64,
+  // 恢复请求 Redis 的时间, 秒
+  down_until: AtomicU64,
+}
+
+impl RedisStore {
+  /// url 如 redis://127.0.0.1:6379/0, 不会立即连接
+  pub fn new(url: &str) -> Result<Self> {
+    Ok(Self {
+      client: Client::open(url)?,
+      conn: Mutex::new(None),
+      script: Script::new(GCRA),
+      timeout: Duration::from_millis(100),
+      retry_sec: 5,
+      down_until: AtomicU64::new(0),
+    })
+  }
+
+  /// 暂时不可用, 应改用本地计数
+  pub fn is_down(&self) -> bool {
+    Clock::now_since_epoch().as_secs() < self.down_until.load(Relaxed)
+  }
+
+  async fn conn(&self) -> Result<MultiplexedConnection> {
+    if let Some(conn) = self.conn.lock().clone() {
+      return Ok(conn);
+    }
+    let conn = self.client.get_multiplexed_async_connection().await?;
+    *self.conn.lock() = Some(conn.clone());
+    Ok(conn)
+  }
+
+  async fn gcra(&self, key: &str, rate: f64, burst: f64) -> Result<u64> {
+    let mut conn = self.conn().await?;
+    let emission = 1000.0 / rate.max(f64::MIN_POSITIVE);
+    let wait: u64 = self
+      .script
+      .key(key)
+      .arg(emission)
+      .arg(burst)
+      .invoke_async(&mut conn)
+      .await?;
+    Ok(wait)
+  }
+
+  /// 取一个令牌, 返回需要等待的毫秒数, 0 表示放行
+  /// 出错或超时时断开连接, retry_sec 秒内 is_down 返回 true
+  pub async fn take(&self, key: &str, rate: f64, burst: f64) -> Result<u64> {
+    let r = match tokio::time::timeout(self.timeout, self.gcra(key, rate, burst)).await {
+      Ok(r) => r,
+      Err(_) => Err(Error::RedisTimeout),
+    };
+    if r.is_err() {
+      *self.conn.lock() = None;
+      self
+        .down_until
+        .store(Clock::now_since_epoch().as_secs() + self.retry_sec, Relaxed);
+    }
+    r
+  }
+}
+
+/// 限流规则在 Redis 中的计数
+#[derive(Debug, Clone)]
+pub struct Shared {
+  pub store: Arc<RedisStore>,
+  // 键的前缀, 各条规则不同, 如 gway:rl:example.com:api
+  pub prefix: FastStr,
+}
@@ -0,0 +1,132 @@
+use faststr::FastStr;
+use http::{
+  HeaderMap, HeaderName, HeaderValue,
+  header::{CONTENT_LOCATION, LOCATION, REFRESH, SET_COOKIE},
+};
+
+use crate::{Result, Tpl, Vars};
+
+/// 改写上游响应中的地址和 cookie, 类似 nginx 的 proxy_redirect 和 proxy_cookie_domain
+#[derive(Debug, Clone, Default)]
+pub struct ResRewrite {
+  // Location, Content-Location, Refresh 中的地址前缀 -> 替换的模板, 如 http://127.0.0.1:9080/ -> https://{host}/
+  pub url_li: Box<[(FastStr, Tpl)]>,
+  // cookie 的 Domain -> 替换的模板, 渲染为空时去掉 Domain
+  pub cookie_domain_li: Box<[(FastStr, Tpl)]>,
+  // cookie 的 Path 前缀 -> 替换的前缀
+  pub cookie_path_li: Box<[(FastStr, FastStr)]>,
+  // 给没有 Secure 的 cookie 加上 Secure
+  pub cookie_secure: bool,
+}
+
+impl ResRewrite {
+  pub fn apply(&self, headers: &mut HeaderMap, vars: &Vars) -> Result<()> {
+    if !self.url_li.is_empty() {
+      for name in [LOCATION, CONTENT_LOCATION] {
+        self.each(headers, name, |v| self.url(v, vars))?;
+      }
+      self.each(headers, REFRESH, |v| self.refresh(v, vars))?;
+    }
+    if !self.cookie_domain_li.is_empty() || !self.cookie_path_li.is_empty() || self.cookie_secure {
+      self.each(headers, SET_COOKIE, |v| Some(self.cookie(v, vars)))?;
+    }
+    Ok(())
+  }
+
+  // 逐个改写同名头的值, 返回 None 时保留原值
+  fn each(
+    &self,
+    headers: &mut HeaderMap,
+    name: HeaderName,
+    rewrite: impl Fn(&str) -> Option<String>,
+  ) -> Result<()> {
+    let li: Vec<HeaderValue> = headers.get_all(&name).iter().cloned().collect();
+    if li.is_empty() {
+      return Ok(());
+    }
+    let mut changed = false;
+    let mut out = Vec::with_capacity(li.len());
+    for v in li {
+      match v.to_str().ok().and_then(&rewrite) {
+        Some(s) => {
+          changed = true;
+          out.push(HeaderValue::from_str(&s)?);
+        }
+        None => out.push(v),
+      }
+    }
+    if changed {
+      headers.remove(&name);
+      for v in out {
+        headers.append(&name, v);
+      }
+    }
+    Ok(())
+  }
+
+  /// 改写地址, 不匹配任何前缀时返回 None
+  pub fn url(&self, url: &str, vars: &Vars) -> Option<String> {
+    self.url_li.iter().find_map(|(from, to)| {
+      let rest = strip_prefix_ignore_case(url, from)?;
+      Some(format!("{}{rest}", to.render(vars)))
+    })
+  }
+
+  // Refresh: 5; url=http://...
+  fn refresh(&self, v: &str, vars: &Vars) -> Option<String> {
+    let pos = v.to_ascii_lowercase().find("url=")? + 4;
+    let url = v[pos..].trim_matches(['\'', '"']);
+    Some(format!("{}{}", &v[..pos], self.url(url, vars)?))
+  }
+
+  fn cookie(&self, v: &str, vars: &Vars) -> String {
+    let mut it = v.split(';');
+    let mut out = it.next().unwrap_or_default().trim().to_owned();
+    let mut secure = false;
+    for attr in it {
+      let attr = attr.trim();
+      if attr.is_empty() {
+        continue;
+      }
+      let (k, val) = attr.split_once('=').unwrap_or((attr, ""));
+      let val = val.trim();
+      if k.eq_ignore_ascii_case("domain") {
+        let domain = val.trim_start_matches('.');
+        if let Some((_, to)) = self
+          .cookie_domain_li
+          .iter()
+          .find(|(from, _)| from.trim_start_matches('.').eq_ignore_ascii_case(domain))
+        {
+          let to = to.render(vars);
+          if !to.is_empty() {
+            out.push_str(&format!("; Domain={to}"));
+          }
+          continue;
+        }
+      } else if k.eq_ignore_ascii_case("path")
+        && let Some((from, to)) = self
+          .cookie_path_li
+          .iter()
+          .find(|(from, _)| val.starts_with(from.as_str()))
+      {
+        out.push_str(&format!("; Path={to}{}", &val[from.len()..]));
+        continue;
+      } else if k.eq_ignore_ascii_case("secure") {
+        secure = true;
+      }
+      out.push_str("; ");
+      out.push_str(attr);
+    }
+    if self.cookie_secure && !secure {
+      out.push_str("; Secure");
+    }
+    out
+  }
+}
+
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+  let head = s.get(..prefix.len())?;
+  head
+    .eq_ignore_ascii_case(prefix)
+    .then(|| &s[prefix.len()..])
+}
@@ -0,0 +1,121 @@
+use std::{
+  sync::atomic::{AtomicU64, Ordering::Relaxed},
+  time::Duration,
+};
+
+use coarsetime::Clock;
+use http::{Method, StatusCode};
+
+// 重试预算的统计窗口, 秒
+const WINDOW_SEC: u64 = 10;
+
+/// 上游的重试策略, 重试次数上限为 Upstream::max_retry
+#[derive(Debug)]
+pub struct Retry {
+  // 请求已发出后失败, 或按状态码重试时, 只重试这些方法
+  pub method_li: Box<[Method]>,
+  // 上游返回这些状态码时换地址重试
+  pub status_li: Box<[StatusCode]>,
+  // 连接失败 (请求未发出) 时重试, 不限方法
+  pub on_connect: bool,
+  // 请求发出后失败时重试
+  pub on_send: bool,
+  // 第 n 次重试前等待 [0, base * 2^(n-1)] 间的随机时长, 不超过 backoff_max
+  pub backoff_base: Duration,
+  pub backoff_max: Duration,
+  // 重试次数不超过请求数的该比例, 加上每个窗口固定允许的 budget_min 次
+  pub budget_ratio: f64,
+  pub budget_min: u64,
+  // 窗口开始时间 (秒), 窗口内的请求数和重试数
+  window: AtomicU64,
+  request: AtomicU64,
+  retry: AtomicU64,
+}
+
+impl Default for Retry {
+  fn default() -> Self {
+    Self {
+      method_li: [
+        Method::GET,
+        Method::HEAD,
+        Method::OPTIONS,
+        Method::PUT,
+        Method::DELETE,
+        Method::TRACE,
+      ]
+      .into(),
+      status_li: [
+        StatusCode::BAD_GATEWAY,
+        StatusCode::SERVICE_UNAVAILABLE,
+        StatusCode::GATEWAY_TIMEOUT,
+      ]
+      .into(),
+      on_connect: true,
+      on_send: true,
+      backoff_base: Duration::from_millis(20),
+      backoff_max: Duration::from_secs(1),
+      budget_ratio: 0.2,
+      budget_min: 10,
+      window: AtomicU64::new(0),
+      request: AtomicU64::new(0),
+      retry: AtomicU64::new(0),
+    }
+  }
+}
+
+impl Retry {
+  // 进入新窗口时清零计数
+  fn roll(&self) {
+    let now = Clock::now_since_epoch().as_secs();
+    let window = self.window.load(Relaxed);
+    if now >= window + WINDOW_SEC
+      && self
+        .window
+        .compare_exchange(window, now, Relaxed, Relaxed)
+        .is_ok()
+    {
+      self.request.store(0, Relaxed);
+      self.retry.store(0, Relaxed);
+    }
+  }
+
+  /// 记录一个请求, 用于计算重试预算
+  pub fn request(&self) {
+    self.roll();
+    self.request.fetch_add(1, Relaxed);
+  }
+
+  /// 预算内时占用一次重试并返回 true
+  pub fn budget(&self) -> bool {
+    self.roll();
+    let request = self.request.load(Relaxed);
+    let allow = self.budget_min + (request as f64 * self.budget_ratio) as u64;
+    self
+      .retry
+      .fetch_update(Relaxed, Relaxed, |n| (n < allow).then_some(n + 1))
+      .is_ok()
+  }
+
+  /// 请求出错后是否可以重试, connect 为连接失败 (请求未发出)
+  pub fn on_error(&self, method: &Method, connect: bool) -> bool {
+    if connect {
+      self.on_connect
+    } else {
+      self.on_send && self.method_li.contains(method)
+    }
+  }
+
+  /// 上游返回 status 后是否可以重试
+  pub fn on_status(&self, method: &Method, status: StatusCode) -> bool {
+    self.status_li.contains(&status) && self.method_li.contains(method)
+  }
+
+  /// 第 n 次重试前的等待时长, 带随机抖动
+  pub fn backoff(&self, n: usize) -> Duration {
+    let exp = self
+      .backoff_base
+      .saturating_mul(1 << (n.saturating_sub(1)).min(16) as u32)
+      .min(self.backoff_max);
+    exp.mul_f64(fastrand::f64())
+  }
+}
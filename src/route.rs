@@ -1,16 +1,94 @@
 use std::{
   collections::{HashMap, HashSet},
-  net::SocketAddr,
+  net::{IpAddr, Ipv4Addr, SocketAddr},
   sync::Arc,
 };
 
 use dashmap::{DashMap, mapref::one::Ref};
 use faststr::FastStr;
+use http::{Extensions, HeaderMap, StatusCode};
+use sub_host::sub_host;
+
+use crate::{
+  Admin, AltSvc, Cache, CacheRule, Compress, Concurrency, HeaderRule, Health, Locale, Mirror, Park,
+  Peer, RateLimit, RedirectMap, ResRewrite, Retry, Rule, SecHeader, Split, Sticky, Tpl,
+  normalize_host, rate_limit,
+};
+
+/// 哪些非规范域名跳转到本站
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Canonical {
+  /// 未配置的子域名跳转到本站
+  #[default]
+  SubHost,
+  /// 不跳转, 未配置的域名返回 404
+  Off,
+  /// 本站为 www.example.com, example.com 跳转到本站
+  ApexToWww,
+  /// 本站为 example.com, www.example.com 跳转到本站
+  WwwToApex,
+  /// 列表中的域名都跳转到本站
+  Alias(Box<[FastStr]>),
+}
+
+impl Canonical {
+  // 需要跳转到 host 的别名
+  fn alias(&self, host: &str) -> Vec<FastStr> {
+    match self {
+      Canonical::SubHost | Canonical::Off => vec![],
+      Canonical::ApexToWww => host
+        .strip_prefix("www.")
+        .map(|apex| vec![FastStr::new(apex)])
+        .unwrap_or_default(),
+      Canonical::WwwToApex => vec![format!("www.{host}").into()],
+      Canonical::Alias(li) => li.to_vec(),
+    }
+  }
+}
+
+/// h1 端口的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum H1 {
+  /// 跳转到 https
+  #[default]
+  Https,
+  /// 直接代理
+  Serve,
+}
 
 #[derive(Debug, Clone)]
 pub struct SiteConf {
   pub upstream: Arc<Upstream>,
   pub cert_host: FastStr,
+  // 批量跳转表, 在转发前查找
+  pub redirect: Option<Arc<RedirectMap>>,
+  pub canonical: Canonical,
+  pub locale: Option<Arc<Locale>>,
+  // 跳转到规范域名的状态码
+  pub canonical_status: StatusCode,
+  pub h1: H1,
+  // 按路径, 方法, 请求头转发到不同上游组的规则, 都不命中时用 upstream
+  pub rule_li: Arc<[Rule]>,
+  // 未命中规则时按权重分流到多个上游组
+  pub split: Option<Arc<Split>>,
+  // 转发给上游的路径模板, 如 /tenant/{0}{uri}
+  pub path_tpl: Option<Tpl>,
+  // 请求头和响应头的增删改规则
+  pub header_rule_li: Arc<[HeaderRule]>,
+  // 改写响应中上游的地址和 cookie 属性
+  pub res_rewrite: Option<Arc<ResRewrite>>,
+  // 安全响应头, 也用于网关自己生成的跳转和错误页
+  pub sec_header: Option<Arc<SecHeader>>,
+  // 为 false 时 Alt-Svc 发送 clear, 不让浏览器用 h3 访问本站
+  pub h3: bool,
+  // 响应压缩, None 时不压缩
+  pub compress: Option<Arc<Compress>>,
+  // 响应缓存策略, None 时不缓存
+  pub cache: Option<Arc<CacheRule>>,
+  // 限流规则, 依次检查, 任一条超限时返回 429
+  pub rate_limit_li: Arc<[RateLimit]>,
+  // 按比例复制请求到影子上游, None 时不镜像
+  pub mirror: Option<Arc<Mirror>>,
 }
 
 impl SiteConf {
@@ -18,15 +96,68 @@ impl SiteConf {
     Self {
       upstream,
       cert_host,
+      redirect: None,
+      canonical: Canonical::default(),
+      locale: None,
+      canonical_status: StatusCode::MOVED_PERMANENTLY,
+      h1: H1::default(),
+      rule_li: Arc::new([]),
+      split: None,
+      path_tpl: None,
+      header_rule_li: Arc::new([]),
+      res_rewrite: None,
+      sec_header: None,
+      h3: true,
+      compress: None,
+      cache: None,
+      rate_limit_li: Arc::new([]),
+      mirror: None,
+    }
+  }
+
+  /// 检查限流, 超限时返回需要等待的秒数
+  pub async fn limited(
+    &self,
+    host: &str,
+    path: &str,
+    headers: &HeaderMap,
+    extensions: &Extensions,
+  ) -> Option<u64> {
+    if self.rate_limit_li.is_empty() {
+      return None;
+    }
+    // 由监听端写入请求扩展
+    let ip = extensions
+      .get::<Peer>()
+      .map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |peer| peer.addr.ip());
+    rate_limit::check(&self.rate_limit_li, host, path, headers, ip).await
+  }
+
+  /// h1 端口上本站的地址前缀
+  pub fn h1_scheme(&self) -> &'static str {
+    match self.h1 {
+      H1::Https => "https",
+      H1::Serve => "http",
     }
   }
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Default)]
 pub enum Protocol {
+  #[default]
   H1,
 }
 
+/// 转发给上游的 Host (h2, h3 的 :authority)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum UpHost {
+  /// 保留客户端请求的域名
+  #[default]
+  Keep,
+  /// 替换为上游自己的虚拟主机名
+  Set(FastStr),
+}
+
 #[derive(Debug)]
 pub struct Upstream {
   pub addr_li: Box<[SocketAddr]>,
@@ -34,6 +165,47 @@ pub struct Upstream {
   pub request_timeout_sec: u64,
   pub max_retry: usize,
   pub protocol: Protocol,
+  // 会话保持, None 时不启用
+  pub sticky: Option<Sticky>,
+  // 请求失败的地址在这段时间内不参与负载均衡
+  pub fail_timeout_sec: u64,
+  pub health: Health,
+  pub host: UpHost,
+  // 自适应并发限制, None 时不限制
+  pub concurrency: Option<Concurrency>,
+  // 所有地址都不可用时暂存请求, 等到有地址恢复再重试, None 时直接返回错误
+  pub park: Option<Arc<Park>>,
+  pub retry: Retry,
+}
+
+impl Default for Upstream {
+  fn default() -> Self {
+    Self {
+      addr_li: Box::default(),
+      connect_timeout_sec: 10,
+      request_timeout_sec: 60,
+      max_retry: 3,
+      protocol: Protocol::default(),
+      sticky: None,
+      fail_timeout_sec: 10,
+      health: Health::default(),
+      host: UpHost::default(),
+      concurrency: None,
+      park: None,
+      retry: Retry::default(),
+    }
+  }
+}
+
+impl Upstream {
+  /// 从 pos 开始找到第一个可用的地址, 都不可用时返回 pos
+  pub fn up_pos(&self, pos: usize) -> usize {
+    let len = self.addr_li.len();
+    (0..len)
+      .map(|i| (pos + i) % len)
+      .find(|&i| self.health.is_up(self.addr_li[i]))
+      .unwrap_or(pos)
+  }
 }
 
 #[derive(Debug)]
@@ -46,6 +218,12 @@ pub struct UpstreamSiteSet {
 pub struct Route {
   pub host_conf: DashMap<FastStr, SiteConf>,
   pub upstream_site: HashMap<FastStr, UpstreamSiteSet>,
+  // 别名 -> 规范域名
+  pub alias: DashMap<FastStr, FastStr>,
+  pub alt_svc: AltSvc,
+  pub cache: Arc<Cache>,
+  // 管理接口, None 时不开放
+  pub admin: Option<Admin>,
 }
 
 impl Route {
@@ -60,6 +238,13 @@ impl Route {
     );
   }
 
+  pub fn upstream(&self, upstream_name: &str) -> Option<Arc<Upstream>> {
+    self
+      .upstream_site
+      .get(upstream_name)
+      .map(|t| t.upstream.clone())
+  }
+
   pub fn set(
     &mut self,
     host: impl Into<FastStr>,
@@ -67,7 +252,7 @@ impl Route {
     upstream_name: impl Into<FastStr>,
   ) -> &mut Self {
     let upstream_name = upstream_name.into();
-    let host = host.into();
+    let host = normalize_host(&host.into());
     if let Some(t) = self.upstream_site.get_mut(&upstream_name) {
       t.host_set.insert(host.clone());
       self
@@ -78,6 +263,77 @@ impl Route {
   }
 
   pub fn conf_by_host(&self, host: &str) -> Option<Ref<'_, FastStr, SiteConf>> {
-    self.host_conf.get(host)
+    self.host_conf.get(&normalize_host(host))
+  }
+
+  /// 设置站点的规范域名策略, 站点不存在时返回 false
+  pub fn set_canonical(
+    &self,
+    host: &str,
+    canonical: Canonical,
+    status: StatusCode,
+    h1: H1,
+  ) -> bool {
+    let host = normalize_host(host);
+    let Some(mut conf) = self.host_conf.get_mut(&host) else {
+      return false;
+    };
+    self.alias.retain(|_, site| site != &host);
+    for alias in canonical.alias(&host) {
+      self.alias.insert(normalize_host(&alias), host.clone());
+    }
+    conf.canonical = canonical;
+    conf.canonical_status = status;
+    conf.h1 = h1;
+    true
+  }
+
+  /// 按域名查找站点, 精确匹配优先, 其次是泛域名
+  /// *.example.com 只匹配一级子域名, **.example.com 匹配多级
+  /// 返回站点配置和泛域名捕获的各段
+  pub fn site(&self, host: &str) -> Option<(SiteConf, Box<[FastStr]>)> {
+    if let Some(conf) = self.host_conf.get(host) {
+      return Some((conf.value().clone(), Box::default()));
+    }
+    // 从最长的后缀开始匹配
+    for (pos, _) in host.match_indices('.') {
+      let (prefix, suffix) = (&host[..pos], &host[pos + 1..]);
+      let one = !prefix.contains('.');
+      let conf = one
+        .then(|| self.host_conf.get(format!("*.{suffix}").as_str()))
+        .flatten()
+        .or_else(|| self.host_conf.get(format!("**.{suffix}").as_str()));
+      if let Some(conf) = conf {
+        let capture = prefix.split('.').map(FastStr::new).collect();
+        return Some((conf.value().clone(), capture));
+      }
+    }
+    None
+  }
+
+  /// 未配置的域名应跳转到的规范域名及其配置
+  pub fn canonical(&self, host: &str) -> Option<(FastStr, SiteConf)> {
+    if let Some(site) = self.alias.get(host).map(|s| s.value().clone())
+      && let Some(conf) = self.host_conf.get(&site)
+    {
+      return Some((site, conf.value().clone()));
+    }
+    let parent = sub_host(host)?;
+    let (conf, _) = self.site(&parent)?;
+    (conf.canonical == Canonical::SubHost).then(|| (parent.into(), conf))
+  }
+
+  /// 修改站点配置, 站点不存在时返回 false
+  pub fn with_site(&self, host: &str, f: impl FnOnce(&mut SiteConf)) -> bool {
+    if let Some(mut conf) = self.host_conf.get_mut(&normalize_host(host)) {
+      f(conf.value_mut());
+      return true;
+    }
+    false
+  }
+
+  /// 设置站点的跳转表, 站点不存在时返回 false
+  pub fn set_redirect(&self, host: &str, redirect: Option<Arc<RedirectMap>>) -> bool {
+    self.with_site(host, |conf| conf.redirect = redirect)
   }
 }
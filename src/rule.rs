@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use faststr::FastStr;
+use http::{HeaderMap, HeaderName, Method};
+use regex::Regex;
+
+use crate::{Hedge, Upstream};
+
+/// 路径匹配方式
+#[derive(Debug, Clone)]
+pub enum PathMatch {
+  Any,
+  Prefix(FastStr),
+  Exact(FastStr),
+  Regex(Regex),
+}
+
+impl PathMatch {
+  pub fn is_match(&self, path: &str) -> bool {
+    match self {
+      PathMatch::Any => true,
+      PathMatch::Prefix(prefix) => path.starts_with(prefix.as_str()),
+      PathMatch::Exact(p) => path == p,
+      PathMatch::Regex(re) => re.is_match(path),
+    }
+  }
+}
+
+/// 站点内的转发规则, 按顺序匹配, 第一条命中的生效
+#[derive(Debug, Clone)]
+pub struct Rule {
+  pub path: PathMatch,
+  // 为空时匹配所有方法
+  pub method_li: Box<[Method]>,
+  // 必须都存在的请求头
+  pub header_li: Box<[HeaderName]>,
+  pub upstream: Arc<Upstream>,
+  // 转发前去掉的路径前缀
+  pub strip: Option<FastStr>,
+  // 去掉前缀后再加上的前缀
+  pub add: Option<FastStr>,
+  // 对冲请求, None 时不对冲
+  pub hedge: Option<Arc<Hedge>>,
+}
+
+impl Rule {
+  pub fn new(path: PathMatch, upstream: Arc<Upstream>) -> Self {
+    Self {
+      path,
+      method_li: Box::default(),
+      header_li: Box::default(),
+      upstream,
+      strip: None,
+      add: None,
+      hedge: None,
+    }
+  }
+
+  pub fn is_match(&self, method: &Method, path: &str, headers: &HeaderMap) -> bool {
+    (self.method_li.is_empty() || self.method_li.contains(method))
+      && self.header_li.iter().all(|h| headers.contains_key(h))
+      && self.path.is_match(path)
+  }
+
+  /// 改写转发给上游的路径, 无需改写时返回 None
+  pub fn rewrite(&self, path_and_query: &str) -> Option<String> {
+    if self.strip.is_none() && self.add.is_none() {
+      return None;
+    }
+    let rest = match &self.strip {
+      Some(strip) => path_and_query
+        .strip_prefix(strip.as_str())
+        .unwrap_or(path_and_query),
+      None => path_and_query,
+    };
+    let add = self
+      .add
+      .as_ref()
+      .map_or("", |add| add.trim_end_matches('/'));
+    Some(if rest.starts_with('/') {
+      format!("{add}{rest}")
+    } else {
+      format!("{add}/{rest}")
+    })
+  }
+}
+
+/// 按顺序找到第一条命中的规则
+pub fn find<'a>(
+  rule_li: &'a [Rule],
+  method: &Method,
+  path: &str,
+  headers: &HeaderMap,
+) -> Option<&'a Rule> {
+  rule_li
+    .iter()
+    .find(|rule| rule.is_match(method, path, headers))
+}
@@ -0,0 +1,58 @@
+use http::{
+  HeaderMap, HeaderName, HeaderValue,
+  header::{
+    CONTENT_SECURITY_POLICY, REFERRER_POLICY, STRICT_TRANSPORT_SECURITY, X_CONTENT_TYPE_OPTIONS,
+    X_FRAME_OPTIONS,
+  },
+};
+
+/// 站点的安全响应头, 为 None 的项不处理
+#[derive(Debug, Clone)]
+pub struct SecHeader {
+  // 只在 TLS 连接上发送
+  pub hsts: Option<HeaderValue>,
+  pub csp: Option<HeaderValue>,
+  pub frame: Option<HeaderValue>,
+  pub referrer: Option<HeaderValue>,
+  pub nosniff: bool,
+  // 覆盖上游已设置的值, 否则只补上缺少的
+  pub overwrite: bool,
+}
+
+impl Default for SecHeader {
+  fn default() -> Self {
+    Self {
+      hsts: Some(HeaderValue::from_static(
+        "max-age=63072000; includeSubDomains; preload",
+      )),
+      csp: None,
+      frame: Some(HeaderValue::from_static("SAMEORIGIN")),
+      referrer: Some(HeaderValue::from_static("strict-origin-when-cross-origin")),
+      nosniff: true,
+      overwrite: false,
+    }
+  }
+}
+
+impl SecHeader {
+  pub fn apply(&self, headers: &mut HeaderMap, tls: bool) {
+    let nosniff = self.nosniff.then(|| HeaderValue::from_static("nosniff"));
+    let li: [(HeaderName, Option<&HeaderValue>); 5] = [
+      (
+        STRICT_TRANSPORT_SECURITY,
+        self.hsts.as_ref().filter(|_| tls),
+      ),
+      (CONTENT_SECURITY_POLICY, self.csp.as_ref()),
+      (X_FRAME_OPTIONS, self.frame.as_ref()),
+      (REFERRER_POLICY, self.referrer.as_ref()),
+      (X_CONTENT_TYPE_OPTIONS, nosniff.as_ref()),
+    ];
+    for (name, val) in li {
+      if let Some(val) = val
+        && (self.overwrite || !headers.contains_key(&name))
+      {
+        headers.insert(name, val.clone());
+      }
+    }
+  }
+}
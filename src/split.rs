@@ -0,0 +1,141 @@
+use std::{
+  hash::{DefaultHasher, Hash, Hasher},
+  sync::{
+    Arc,
+    atomic::{AtomicU32, Ordering::Relaxed},
+  },
+};
+
+use faststr::FastStr;
+use http::{HeaderMap, HeaderName};
+
+use crate::{Upstream, cookie};
+
+/// 参与分流的上游组
+#[derive(Debug)]
+pub struct Group {
+  pub name: FastStr,
+  pub upstream: Arc<Upstream>,
+  weight: AtomicU32,
+}
+
+impl Group {
+  pub fn new(name: impl Into<FastStr>, upstream: Arc<Upstream>, weight: u32) -> Self {
+    Self {
+      name: name.into(),
+      upstream,
+      weight: AtomicU32::new(weight),
+    }
+  }
+
+  pub fn weight(&self) -> u32 {
+    self.weight.load(Relaxed)
+  }
+}
+
+/// 按权重在多个上游组之间分流, 用于灰度发布
+#[derive(Debug)]
+pub struct Split {
+  pub group_li: Box<[Group]>,
+  // 请求头的值为组名时固定到该组, 不受权重影响
+  pub pin_header: Option<HeaderName>,
+  // 按该请求头的值哈希分组, 如用户 id, 同一个值总是分到同一组
+  pub key_header: Option<HeaderName>,
+  // 记录分组的 cookie, 为 None 时不写 cookie
+  pub cookie: Option<FastStr>,
+  pub cookie_max_age: u64,
+}
+
+/// 分流结果
+#[derive(Debug)]
+pub struct Pick<'a> {
+  pub group: &'a Group,
+  // 需要写给客户端的 Set-Cookie
+  pub set_cookie: Option<String>,
+}
+
+impl Split {
+  pub fn new(group_li: impl Into<Box<[Group]>>) -> Self {
+    Self {
+      group_li: group_li.into(),
+      pin_header: None,
+      key_header: None,
+      cookie: None,
+      cookie_max_age: 86400,
+    }
+  }
+
+  pub fn group(&self, name: &str) -> Option<&Group> {
+    self.group_li.iter().find(|g| g.name == name)
+  }
+
+  /// 运行时调整权重, 组不存在时返回 false
+  pub fn set_weight(&self, name: &str, weight: u32) -> bool {
+    if let Some(group) = self.group(name) {
+      group.weight.store(weight, Relaxed);
+      return true;
+    }
+    false
+  }
+
+  // 落在 [0, 总权重) 中的位置对应的组
+  fn by_pos(&self, pos: u64) -> Option<&Group> {
+    let mut pos = pos;
+    for group in self.group_li.iter() {
+      let weight = group.weight() as u64;
+      if pos < weight {
+        return Some(group);
+      }
+      pos -= weight;
+    }
+    None
+  }
+
+  pub fn pick(&self, headers: &HeaderMap) -> Option<Pick<'_>> {
+    let pick = |group| Pick {
+      group,
+      set_cookie: None,
+    };
+
+    if let Some(name) = &self.pin_header
+      && let Some(group) = headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| self.group(v))
+    {
+      return Some(pick(group));
+    }
+
+    // 权重降为 0 的组不再保持, 便于回滚
+    if let Some(name) = &self.cookie
+      && let Some(group) = cookie::get(headers, name).and_then(|v| self.group(v))
+      && group.weight() > 0
+    {
+      return Some(pick(group));
+    }
+
+    let total: u64 = self.group_li.iter().map(|g| g.weight() as u64).sum();
+    if total == 0 {
+      return None;
+    }
+
+    if let Some(name) = &self.key_header
+      && let Some(key) = headers.get(name)
+    {
+      let mut hasher = DefaultHasher::new();
+      key.as_bytes().hash(&mut hasher);
+      return self.by_pos(hasher.finish() % total).map(pick);
+    }
+
+    let group = self.by_pos(fastrand::u64(0..total))?;
+    Some(Pick {
+      group,
+      set_cookie: self.cookie.as_ref().map(|name| {
+        format!(
+          "{name}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Lax",
+          group.name, self.cookie_max_age
+        )
+      }),
+    })
+  }
+}
@@ -1,35 +1,37 @@
 use std::sync::Arc;
 
-use http_body_util::Full;
+use http_body_util::{BodyExt, Full};
 use hyper::{
   Request, Response, StatusCode, body::Bytes, header::HeaderValue, server::conn::http1,
   service::service_fn,
 };
 use hyper_util::rt::TokioIo;
 use parking_lot::RwLock;
-use sub_host::sub_host;
 use tokio::net::TcpListener;
 
-use crate::{Result, Route, req_host};
+use crate::{Error, H1, Peer, Result, Route, proxy, req_host};
+
+type BoxBody = http_body_util::combinators::BoxBody<Bytes, Error>;
 
 // 根据状态码生成响应
-fn response(status: StatusCode) -> Response<Full<Bytes>> {
+fn response(status: StatusCode) -> Response<BoxBody> {
   let body = if status.is_redirection() {
     Bytes::new()
   } else {
     Bytes::from(status.canonical_reason().unwrap_or_default())
   };
-  let mut res = Response::new(Full::new(body));
+  let mut res = Response::new(Full::new(body).map_err(|never| match never {}).boxed());
   *res.status_mut() = status;
   res
 }
 
-// 所有请求都跳转到 https
-async fn redirect(
+// 默认跳转到 https, 站点配置为 H1::Serve 时直接代理
+async fn serve(
   req: Request<hyper::body::Incoming>,
   route: Arc<Route>,
-) -> Result<Response<Full<Bytes>>, hyper::Error> {
+) -> Result<Response<BoxBody>, hyper::Error> {
   let host = req_host(&req);
+  let host = host.as_str();
 
   let pq = req
     .uri()
@@ -37,25 +39,62 @@ async fn redirect(
     .map(|p| p.as_str())
     .unwrap_or("/");
 
-  let host = if route.host_conf.contains_key(host) {
-    host.to_owned()
-  } else if let Some(h) = sub_host(host)
-    && route.host_conf.contains_key(h.as_str())
-  {
-    h
+  let version = req.version();
+  let (mut res, conf) = if let Some((conf, _)) = route.site(host) {
+    let scheme = conf.h1_scheme();
+    // 直接代理时由 proxy 限流
+    let res = if conf.h1 != H1::Serve
+      && let Some(retry_after) = conf
+        .limited(host, req.uri().path(), req.headers(), req.extensions())
+        .await
+    {
+      let mut res = response(StatusCode::TOO_MANY_REQUESTS);
+      res
+        .headers_mut()
+        .insert("Retry-After", HeaderValue::from(retry_after));
+      res
+    } else {
+      // 命中跳转表时直接跳到最终地址, 省掉一次跳转
+      match conf.redirect.as_ref().and_then(|r| r.get(pq)) {
+        Some((status, location)) if location.starts_with('/') => {
+          jump(status, &format!("{scheme}://{host}{location}"))
+        }
+        Some((status, location)) => jump(status, &location),
+        None if conf.h1 == H1::Serve => return Ok(proxy(req, route).await),
+        None => jump(
+          StatusCode::MOVED_PERMANENTLY,
+          &format!("{scheme}://{host}{pq}"),
+        ),
+      }
+    };
+    (res, conf)
+  } else if let Some((site, conf)) = route.canonical(host) {
+    // 跳转到规范域名, 安全响应头按目标站点的配置
+    let res = jump(
+      conf.canonical_status,
+      &format!("{}://{site}{pq}", conf.h1_scheme()),
+    );
+    (res, conf)
   } else {
     return Ok(response(StatusCode::NOT_FOUND));
   };
 
-  let new_uri = format!("https://{}{}", host, pq);
-  Ok(match HeaderValue::from_str(&new_uri) {
+  if let Some(sec_header) = &conf.sec_header {
+    sec_header.apply(res.headers_mut(), false);
+  }
+  route.alt_svc.apply(res.headers_mut(), version, conf.h3);
+  Ok(res)
+}
+
+fn jump(status: StatusCode, location: &str) -> Response<BoxBody> {
+  match HeaderValue::from_str(location) {
     Ok(location) => {
-      let mut res = response(StatusCode::MOVED_PERMANENTLY);
+      let mut res = response(status);
       res.headers_mut().insert("Location", location);
       res
     }
     Err(_) => response(StatusCode::INTERNAL_SERVER_ERROR),
-  })
+  }
 }
 
 pub async fn srv(
@@ -69,7 +108,7 @@ pub async fn srv(
   loop {
     tokio::select! {
         res = listener.accept() => {
-            let (stream, _) = match res {
+            let (stream, remote_addr) = match res {
                 Ok(val) => val,
                 Err(e) => {
                     log::warn!("h1 accept error: {e}");
@@ -86,7 +125,10 @@ pub async fn srv(
                 async move {
                 let _guard = conn_lock.read();
                 if let Err(err) = http1::Builder::new()
-                    .serve_connection(io, service_fn(move |req| redirect(req, route.clone())))
+                    .serve_connection(io, service_fn(move |mut req: Request<hyper::body::Incoming>| {
+                        req.extensions_mut().insert(Peer::new(remote_addr, false));
+                        serve(req, route.clone())
+                    }))
                     .await
                 {
                     log::warn!("h1: {:?}", err);
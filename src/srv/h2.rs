@@ -7,7 +7,7 @@ use tokio::net::TcpListener;
 use tokio_rustls::rustls::{self, ServerConfig};
 
 use crate::{
-  Result, Route,
+  Peer, Result, Route,
   cert_loader::{CertLoad, CertLoader},
   proxy,
 };
@@ -24,7 +24,7 @@ pub async fn srv<D: CertLoad>(
   loop {
     tokio::select! {
         res = listener.accept() => {
-            let (stream, _remote_addr) = match res {
+            let (stream, remote_addr) = match res {
                 Ok(val) => val,
                 Err(e) => {
                     log::warn!("h2 accept error: {e}");
@@ -87,7 +87,8 @@ pub async fn srv<D: CertLoad>(
                 };
 
                 let io = TokioIo::new(stream);
-                let service = service_fn(move |req: Request<Incoming>| {
+                let service = service_fn(move |mut req: Request<Incoming>| {
+                    req.extensions_mut().insert(Peer::new(remote_addr, true));
                     let route = route.clone();
                     async move { Ok::<_, hyper::Error>(proxy(req, route).await) }
                 });
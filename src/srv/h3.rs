@@ -17,7 +17,7 @@ use s2n_quic::{
 };
 
 use super::s2n_quic as h3_quic;
-use crate::{CertLoad, CertLoader, Error, Result, proxy, route::Route};
+use crate::{CertLoad, CertLoader, Error, Peer, Result, proxy, route::Route};
 
 struct Cert<D: CertLoad> {
   cert_loader: Arc<CertLoader<D>>,
@@ -101,6 +101,7 @@ pub async fn srv<D: CertLoad>(
 }
 
 async fn handle_conn(conn: QuicConnection, route: Arc<Route>) -> Result<()> {
+  let peer = Peer::new(conn.remote_addr()?, true);
   let quic_conn = h3_quic::Connection::new(conn);
   let mut h3_conn = h3::server::Connection::new(quic_conn).await?;
 
@@ -110,7 +111,7 @@ async fn handle_conn(conn: QuicConnection, route: Arc<Route>) -> Result<()> {
         let route = route.clone();
         tokio::spawn(async move {
           if let Ok((req, stream)) = resolver.resolve_request().await
-            && let Err(e) = handle_req(req, stream, route).await
+            && let Err(e) = handle_req(req, stream, route, peer).await
           {
             log::warn!("h3 request error: {e}");
           }
@@ -161,8 +162,10 @@ async fn handle_req(
   req: Request<()>,
   stream: RequestStream<h3_quic::BidiStream<Bytes>, Bytes>,
   route: Arc<Route>,
+  peer: Peer,
 ) -> Result<()> {
-  let (parts, _) = req.into_parts();
+  let (mut parts, _) = req.into_parts();
+  parts.extensions.insert(peer);
   let (mut send_stream, recv_stream) = stream.split();
   let body = http_body_util::BodyStream::new(H3Body::new(recv_stream)).boxed();
   let req = Request::from_parts(parts, body);
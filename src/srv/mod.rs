@@ -87,6 +87,8 @@ pub async fn srv<D: CertLoad, F: Future<Output = ()> + Send + 'static>(
   let h1_listener = get_or_create_tcp_listener(&mut tcp_listeners, h1_addr).await?;
   let h2_listener = get_or_create_tcp_listener(&mut tcp_listeners, h2_addr).await?;
   let h3_socket = get_or_create_udp_socket(&mut udp_listeners, h3_addr).await?;
+  // 按 h3 实际绑定的端口通告 Alt-Svc
+  route.alt_svc.set_port(h3_socket.local_addr()?.port());
 
   srv.spawn(
     "h1",
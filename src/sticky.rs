@@ -0,0 +1,70 @@
+use std::{fmt::Write, net::SocketAddr};
+
+use faststr::FastStr;
+use hmac::{Hmac, Mac};
+use http::HeaderMap;
+use sha2::Sha256;
+
+use crate::cookie;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// cookie 中保留的签名字节数
+const SIGN_LEN: usize = 16;
+
+/// 会话保持: 用带签名的 cookie 记住后端地址, 不暴露真实地址
+#[derive(Debug, Clone)]
+pub struct Sticky {
+  pub cookie: FastStr,
+  key: Box<[u8]>,
+}
+
+impl Sticky {
+  pub fn new(cookie: impl Into<FastStr>, key: impl AsRef<[u8]>) -> Self {
+    Self {
+      cookie: cookie.into(),
+      key: key.as_ref().into(),
+    }
+  }
+
+  fn mac(&self, addr: SocketAddr) -> Option<HmacSha256> {
+    let mut mac = HmacSha256::new_from_slice(&self.key).ok()?;
+    mac.update(addr.to_string().as_bytes());
+    Some(mac)
+  }
+
+  /// 地址对应的 cookie 值
+  pub fn sign(&self, addr: SocketAddr) -> String {
+    let mut out = String::with_capacity(SIGN_LEN * 2);
+    if let Some(mac) = self.mac(addr) {
+      for b in &mac.finalize().into_bytes()[..SIGN_LEN] {
+        let _ = write!(out, "{b:02x}");
+      }
+    }
+    out
+  }
+
+  /// 从请求的 cookie 中找到对应的地址下标, 签名不对或地址已不存在时返回 None
+  pub fn find(&self, headers: &HeaderMap, addr_li: &[SocketAddr]) -> Option<usize> {
+    let val = cookie::get(headers, &self.cookie)?;
+    if val.len() != SIGN_LEN * 2 {
+      return None;
+    }
+    let sign = (0..SIGN_LEN)
+      .map(|i| u8::from_str_radix(val.get(i * 2..i * 2 + 2)?, 16).ok())
+      .collect::<Option<Vec<u8>>>()?;
+    addr_li.iter().position(|addr| {
+      self
+        .mac(*addr)
+        .is_some_and(|mac| mac.verify_truncated_left(&sign).is_ok())
+    })
+  }
+
+  pub fn set_cookie(&self, addr: SocketAddr) -> String {
+    format!(
+      "{}={}; Path=/; HttpOnly; SameSite=Lax",
+      self.cookie,
+      self.sign(addr)
+    )
+  }
+}
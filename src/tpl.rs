@@ -0,0 +1,102 @@
+use std::str::FromStr;
+
+use faststr::FastStr;
+
+use crate::{Error, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Var {
+  // 泛域名捕获的第 n 段
+  Capture(usize),
+  Host,
+  Path,
+  Query,
+  // 路径和查询参数
+  Uri,
+  ClientIp,
+  RequestId,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Seg {
+  Lit(FastStr),
+  Var(Var),
+}
+
+/// 简单模板, 变量写在花括号中, 如 /tenant/{0}{uri}
+/// 可用变量: {0} {1} .. 泛域名捕获的各段, {host} {path} {query} {uri} {client_ip} {request_id}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tpl(Box<[Seg]>);
+
+/// 渲染模板用到的变量
+#[derive(Debug, Default)]
+pub struct Vars<'a> {
+  pub capture: &'a [FastStr],
+  pub host: &'a str,
+  pub path_and_query: &'a str,
+  pub client_ip: &'a str,
+  pub request_id: &'a str,
+}
+
+impl FromStr for Tpl {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    let mut seg_li = Vec::new();
+    let mut rest = s;
+    while let Some(begin) = rest.find('{') {
+      if begin > 0 {
+        seg_li.push(Seg::Lit(FastStr::new(&rest[..begin])));
+      }
+      let end = rest[begin..]
+        .find('}')
+        .ok_or_else(|| Error::Tpl(format!("缺少 }}: {s}")))?
+        + begin;
+      let name = &rest[begin + 1..end];
+      let var = match name {
+        "host" => Var::Host,
+        "path" => Var::Path,
+        "query" => Var::Query,
+        "uri" => Var::Uri,
+        "client_ip" => Var::ClientIp,
+        "request_id" => Var::RequestId,
+        _ => Var::Capture(
+          name
+            .parse()
+            .map_err(|_| Error::Tpl(format!("未知变量 {name}: {s}")))?,
+        ),
+      };
+      seg_li.push(Seg::Var(var));
+      rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() {
+      seg_li.push(Seg::Lit(FastStr::new(rest)));
+    }
+    Ok(Self(seg_li.into()))
+  }
+}
+
+impl Tpl {
+  pub fn render(&self, vars: &Vars) -> String {
+    let (path, query) = vars
+      .path_and_query
+      .split_once('?')
+      .unwrap_or((vars.path_and_query, ""));
+    let mut out = String::new();
+    for seg in &self.0 {
+      match seg {
+        Seg::Lit(s) => out.push_str(s),
+        Seg::Var(var) => out.push_str(match var {
+          Var::Capture(n) => vars.capture.get(*n).map_or("", |c| c.as_str()),
+          Var::Host => vars.host,
+          Var::Path => path,
+          Var::Query => query,
+          Var::Uri => vars.path_and_query,
+          Var::ClientIp => vars.client_ip,
+          Var::RequestId => vars.request_id,
+        }),
+      }
+    }
+    out
+  }
+}
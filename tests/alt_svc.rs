@@ -0,0 +1,58 @@
+mod comm;
+
+use std::sync::Arc;
+
+use http_body_util::Full;
+use hyper::{Request, Version, body::Bytes};
+
+#[tokio::test]
+async fn test_alt_svc() -> anyhow::Result<()> {
+  let addr = comm::upstream().await?;
+  let mut route = comm::route(addr);
+  route.set("no-h3.test", "no-h3.test", "up");
+  route.with_site("no-h3.test", |conf| conf.h3 = false);
+  let route = Arc::new(route);
+
+  let get = async |host: &str, version: Version| -> anyhow::Result<Option<String>> {
+    let req = Request::builder()
+      .uri("/")
+      .version(version)
+      .header("host", host)
+      .body(Full::new(Bytes::new()))?;
+    let res = gway::proxy(req, route.clone()).await;
+    Ok(
+      res
+        .headers()
+        .get("alt-svc")
+        .map(|v| v.to_str())
+        .transpose()?
+        .map(String::from),
+    )
+  };
+
+  // 未启用 h3 时不通告
+  assert_eq!(get("a.test", Version::HTTP_2).await?, None);
+
+  route.alt_svc.set_port(8443);
+  route.alt_svc.set_ma(3600);
+  assert_eq!(
+    get("a.test", Version::HTTP_2).await?.as_deref(),
+    Some("h3=\":8443\"; ma=3600")
+  );
+  assert_eq!(get("a.test", Version::HTTP_11).await?, None);
+  assert_eq!(get("a.test", Version::HTTP_3).await?, None);
+  assert_eq!(
+    get("no-h3.test", Version::HTTP_2).await?.as_deref(),
+    Some("clear")
+  );
+
+  route.alt_svc.set_h1(true);
+  assert!(get("a.test", Version::HTTP_11).await?.is_some());
+
+  route.alt_svc.set_on(false);
+  assert_eq!(
+    get("a.test", Version::HTTP_2).await?.as_deref(),
+    Some("clear")
+  );
+  Ok(())
+}
@@ -0,0 +1,186 @@
+mod comm;
+
+use std::{sync::Arc, time::Duration};
+
+use gway::{CacheRule, PathMatch, Rule};
+use http_body_util::{BodyExt, Full};
+use hyper::{HeaderMap, Request, StatusCode, body::Bytes};
+
+#[tokio::test]
+async fn test_cache() -> anyhow::Result<()> {
+  let addr = comm::upstream().await?;
+  let mut route = comm::route(addr);
+  let beta = comm::upstream().await?;
+  route.add_upstream("beta", comm::up(beta));
+  let beta_up = route.upstream("beta").ok_or(anyhow::anyhow!("no beta"))?;
+  let mut rule = Rule::new(PathMatch::Exact("/fresh".into()), beta_up);
+  rule.header_li = ["x-beta".parse()?].into();
+  route.with_site("a.test", |conf| {
+    conf.cache = Some(Arc::new(CacheRule::default()));
+    conf.rule_li = [rule].into();
+  });
+  let route = Arc::new(route);
+
+  let get = async |path: &str,
+                   header_li: &[(&str, &str)]|
+         -> anyhow::Result<(StatusCode, HeaderMap, Bytes)> {
+    let mut req = Request::builder().uri(path).header("host", "a.test");
+    for (k, v) in header_li {
+      req = req.header(*k, *v);
+    }
+    let res = gway::proxy(req.body(Full::new(Bytes::new()))?, route.clone()).await;
+    let status = res.status();
+    let headers = res.headers().clone();
+    Ok((status, headers, res.into_body().collect().await?.to_bytes()))
+  };
+  let n = |headers: &HeaderMap| headers["x-n"].to_str().map(String::from);
+
+  let cc = ("x-set-cache-control", "max-age=60");
+  let (_, h1, b1) = get("/fresh", &[cc]).await?;
+  let (_, h2, b2) = get("/fresh", &[cc]).await?;
+  assert_eq!(n(&h1)?, n(&h2)?);
+  assert_eq!(b1, b2);
+  assert_eq!(h2["age"], "0");
+  // 客户端要求不用缓存
+  let (_, h3, _) = get("/fresh", &[cc, ("cache-control", "no-cache")]).await?;
+  assert_ne!(n(&h1)?, n(&h3)?);
+  // 规则选了其他上游时不用缓存
+  let (_, h4, _) = get("/fresh", &[cc, ("x-beta", "1")]).await?;
+  assert_eq!(h4["x-upstream"], beta.to_string());
+  let (_, h5, _) = get("/fresh", &[cc]).await?;
+  assert_eq!(h5["x-upstream"], addr.to_string());
+
+  // 缓存键用规范化的域名
+  let (_, h6, _) = get("/fresh", &[cc]).await?;
+  let req = Request::builder()
+    .uri("/fresh")
+    .header("host", "A.Test:443")
+    .header(cc.0, cc.1)
+    .body(Full::new(Bytes::new()))?;
+  let res = gway::proxy(req, route.clone()).await;
+  assert_eq!(n(res.headers())?, n(&h6)?);
+
+  // Vary 不同的请求分开缓存
+  let vary = ("x-set-vary", "Accept-Language");
+  let (_, en1, _) = get("/vary", &[cc, vary, ("accept-language", "en")]).await?;
+  let (_, zh1, _) = get("/vary", &[cc, vary, ("accept-language", "zh")]).await?;
+  let (_, en2, _) = get("/vary", &[cc, vary, ("accept-language", "en")]).await?;
+  assert_ne!(n(&en1)?, n(&zh1)?);
+  assert_eq!(n(&en1)?, n(&en2)?);
+
+  let no_store = ("x-set-cache-control", "no-store");
+  let (_, h1, _) = get("/no-store", &[no_store]).await?;
+  let (_, h2, _) = get("/no-store", &[no_store]).await?;
+  assert_ne!(n(&h1)?, n(&h2)?);
+
+  // 过期后用 ETag 向上游验证, 304 时返回缓存的内容
+  let revalidate = [
+    ("x-set-cache-control", "max-age=1"),
+    ("x-set-etag", "\"v1\""),
+  ];
+  let (_, h1, b1) = get("/etag", &revalidate).await?;
+  tokio::time::sleep(Duration::from_millis(1100)).await;
+  let (status, h2, b2) = get("/etag", &revalidate).await?;
+  assert_eq!(status, StatusCode::OK);
+  assert_eq!(n(&h1)?, n(&h2)?);
+  assert_eq!(b1, b2);
+
+  // 客户端的条件请求由缓存直接回答
+  let mut inm = revalidate.to_vec();
+  inm.push(("if-none-match", "\"v1\""));
+  let (status, _, body) = get("/etag", &inm).await?;
+  assert_eq!(status, StatusCode::NOT_MODIFIED);
+  assert!(body.is_empty());
+
+  // 超出字节上限时淘汰最久未用的
+  route.cache.set_max_byte(4096);
+  for i in 0..20 {
+    get(&format!("/lru/{i}"), &[cc]).await?;
+  }
+  assert!(route.cache.byte() <= 4096);
+  assert!(route.cache.len() < 20);
+  Ok(())
+}
+
+#[tokio::test]
+async fn test_cache_stale() -> anyhow::Result<()> {
+  let addr = comm::upstream().await?;
+  // 只响应一次就关闭的上游, 模拟上游整组宕机
+  let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+  let down_addr = listener.local_addr()?;
+  let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+  let down = tokio::spawn(async move {
+    let app = axum::Router::new().fallback(|| async { ([("cache-control", "max-age=1")], "ok") });
+    axum::serve(listener, app)
+      .with_graceful_shutdown(async {
+        let _ = rx.await;
+      })
+      .await
+  });
+
+  let mut route = comm::route(addr);
+  route.add_upstream("down", comm::up(down_addr));
+  route.set("down.test", "down.test", "down");
+  let rule = Arc::new(CacheRule {
+    stale_error: 60,
+    ..Default::default()
+  });
+  for host in ["a.test", "down.test"] {
+    route.with_site(host, |conf| conf.cache = Some(rule.clone()));
+  }
+  let route = Arc::new(route);
+
+  let get =
+    async |host: &str, path: &str, header_li: &[(&str, &str)]| -> anyhow::Result<HeaderMap> {
+      let mut req = Request::builder().uri(path).header("host", host);
+      for (k, v) in header_li {
+        req = req.header(*k, *v);
+      }
+      let res = gway::proxy(req.body(Full::new(Bytes::new()))?, route.clone()).await;
+      let headers = res.headers().clone();
+      res.into_body().collect().await?;
+      Ok(headers)
+    };
+  let n = |headers: &HeaderMap| headers["x-n"].to_str().map(String::from);
+
+  // 后台刷新: 先返回旧内容, 刷新完成后返回新内容
+  let swr = (
+    "x-set-cache-control",
+    "max-age=1, stale-while-revalidate=30",
+  );
+  let h1 = get("a.test", "/swr", &[swr]).await?;
+  tokio::time::sleep(Duration::from_millis(1100)).await;
+  let h2 = get("a.test", "/swr", &[swr]).await?;
+  assert_eq!(n(&h1)?, n(&h2)?);
+  assert_eq!(h2["x-cache"], "stale");
+  tokio::time::sleep(Duration::from_millis(200)).await;
+  let h3 = get("a.test", "/swr", &[swr]).await?;
+  assert_ne!(n(&h1)?, n(&h3)?);
+  assert!(!h3.contains_key("x-cache"));
+
+  // 上游返回 5xx
+  let cc = ("x-set-cache-control", "max-age=1");
+  let must = (
+    "x-set-cache-control",
+    "max-age=1, stale-while-revalidate=30, must-revalidate",
+  );
+  let h1 = get("a.test", "/5xx", &[cc, ("x-status", "200")]).await?;
+  let m1 = get("a.test", "/must", &[must, ("x-status", "200")]).await?;
+  tokio::time::sleep(Duration::from_millis(1100)).await;
+  let h2 = get("a.test", "/5xx", &[cc, ("x-status", "503")]).await?;
+  assert_eq!(n(&h1)?, n(&h2)?);
+  assert_eq!(h2["x-cache"], "stale");
+  // must-revalidate 过期后不用旧内容
+  let m2 = get("a.test", "/must", &[must, ("x-status", "503")]).await?;
+  assert_ne!(n(&m1)?, n(&m2)?);
+  assert!(!m2.contains_key("x-cache"));
+
+  // 上游连不上
+  get("down.test", "/", &[]).await?;
+  let _ = tx.send(());
+  down.await??;
+  tokio::time::sleep(Duration::from_millis(1100)).await;
+  let headers = get("down.test", "/", &[]).await?;
+  assert_eq!(headers["x-cache"], "stale");
+  Ok(())
+}
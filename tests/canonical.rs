@@ -0,0 +1,92 @@
+mod comm;
+
+use std::sync::Arc;
+
+use gway::{Canonical, H1, Route};
+use http_body_util::Full;
+use hyper::{Request, StatusCode, body::Bytes};
+
+async fn get(route: &Arc<Route>, host: &str) -> anyhow::Result<(StatusCode, Option<String>)> {
+  let req = Request::builder()
+    .uri("/p?q=1")
+    .header("host", host)
+    .body(Full::new(Bytes::new()))?;
+  let res = gway::proxy(req, route.clone()).await;
+  let location = res
+    .headers()
+    .get("location")
+    .and_then(|l| l.to_str().ok())
+    .map(Into::into);
+  Ok((res.status(), location))
+}
+
+#[tokio::test]
+async fn test_canonical() -> anyhow::Result<()> {
+  let mut route = comm::route("127.0.0.1:1".parse()?);
+  route.set("www.d.test", "d.test", "up");
+  route.set("b.test", "b.test", "up");
+  route.set("c.test", "c.test", "up");
+  let route = Arc::new(route);
+
+  // 默认: 子域名跳转到父域名
+  assert_eq!(
+    get(&route, "x.b.test").await?,
+    (
+      StatusCode::MOVED_PERMANENTLY,
+      Some("https://b.test/p?q=1".into())
+    )
+  );
+
+  // apex -> www
+  route.set_canonical(
+    "www.d.test",
+    Canonical::ApexToWww,
+    StatusCode::FOUND,
+    H1::Https,
+  );
+  assert_eq!(
+    get(&route, "d.test").await?,
+    (StatusCode::FOUND, Some("https://www.d.test/p?q=1".into()))
+  );
+
+  // 关闭后未配置的子域名返回 404
+  route.set_canonical(
+    "b.test",
+    Canonical::Off,
+    StatusCode::MOVED_PERMANENTLY,
+    H1::Https,
+  );
+  assert_eq!(get(&route, "x.b.test").await?.0, StatusCode::NOT_FOUND);
+
+  // 别名列表
+  route.set_canonical(
+    "c.test",
+    Canonical::Alias(["old.test".into(), "legacy.test".into()].into()),
+    StatusCode::PERMANENT_REDIRECT,
+    H1::Serve,
+  );
+  assert_eq!(
+    get(&route, "legacy.test").await?,
+    (
+      StatusCode::PERMANENT_REDIRECT,
+      Some("https://c.test/p?q=1".into())
+    )
+  );
+
+  // 重新设置时移除旧的别名
+  route.set_canonical(
+    "c.test",
+    Canonical::WwwToApex,
+    StatusCode::MOVED_PERMANENTLY,
+    H1::Https,
+  );
+  assert_eq!(get(&route, "old.test").await?.0, StatusCode::NOT_FOUND);
+  assert_eq!(
+    get(&route, "www.c.test").await?,
+    (
+      StatusCode::MOVED_PERMANENTLY,
+      Some("https://c.test/p?q=1".into())
+    )
+  );
+  Ok(())
+}
@@ -1,2 +1,8 @@
+#![allow(dead_code, unused_imports)]
+
 mod randstr;
+mod route;
+mod upstream;
 pub use randstr::randstr;
+pub use route::{route, route_to, up};
+pub use upstream::{upstream, upstream_on};
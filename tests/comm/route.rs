@@ -0,0 +1,28 @@
+use std::net::SocketAddr;
+
+use gway::{Protocol, Route, Upstream};
+
+/// 测试用的上游: 单个地址, h1, 超时 1 秒, 不重试
+pub fn up(addr: SocketAddr) -> Upstream {
+  Upstream {
+    addr_li: vec![addr].into(),
+    connect_timeout_sec: 1,
+    request_timeout_sec: 1,
+    max_retry: 0,
+    protocol: Protocol::H1,
+    ..Default::default()
+  }
+}
+
+/// 站点 a.test 转发到上游 up 的路由
+pub fn route_to(upstream: Upstream) -> Route {
+  let mut route = Route::default();
+  route.add_upstream("up", upstream);
+  route.set("a.test", "a.test", "up");
+  route
+}
+
+/// 站点 a.test 转发到 addr 的路由
+pub fn route(addr: SocketAddr) -> Route {
+  route_to(up(addr))
+}
@@ -0,0 +1,87 @@
+use std::{
+  net::SocketAddr,
+  sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering::Relaxed},
+  },
+};
+
+use axum::{
+  Router,
+  extract::Request,
+  http::{HeaderName, HeaderValue, StatusCode},
+  response::{IntoResponse, Response},
+};
+
+// 回显请求: 响应体为 host + uri, 请求头以 x-req- 前缀原样返回, x-upstream 为上游地址
+// 请求头 x-set-<name> 作为响应头 <name> 返回, 用于模拟上游的响应头
+// x-n 为本上游收到的第几个请求, If-None-Match 与 x-set-etag 相同时返回 304, 请求头 x-status 为响应状态码, x-sleep-ms 为响应前等待的毫秒数
+async fn echo(addr: SocketAddr, n: Arc<AtomicU64>, req: Request) -> Response {
+  let n = n.fetch_add(1, Relaxed) + 1;
+  if let Some(ms) = req
+    .headers()
+    .get("x-sleep-ms")
+    .and_then(|v| v.to_str().ok())
+    .and_then(|v| v.parse().ok())
+  {
+    tokio::time::sleep(std::time::Duration::from_millis(ms)).await;
+  }
+  let etag = req.headers().get("x-set-etag");
+  if etag.is_some() && etag == req.headers().get("if-none-match") {
+    return StatusCode::NOT_MODIFIED.into_response();
+  }
+  let host = req
+    .headers()
+    .get("host")
+    .and_then(|h| h.to_str().ok())
+    .unwrap_or_default()
+    .to_owned();
+  let mut res = format!("{host}{}", req.uri()).into_response();
+  for (k, v) in req.headers() {
+    if let Ok(name) = HeaderName::try_from(format!("x-req-{k}")) {
+      res.headers_mut().append(name, v.clone());
+    }
+  }
+  for k in req.headers().keys() {
+    if let Some(name) = k.as_str().strip_prefix("x-set-") {
+      res.headers_mut().remove(name);
+    }
+  }
+  for (k, v) in req.headers() {
+    if let Some(name) = k.as_str().strip_prefix("x-set-")
+      && let Ok(name) = HeaderName::try_from(name)
+    {
+      res.headers_mut().append(name, v.clone());
+    }
+  }
+  if let Ok(method) = HeaderValue::from_str(req.method().as_str()) {
+    res.headers_mut().insert("x-method", method);
+  }
+  if let Ok(addr) = HeaderValue::from_str(&addr.to_string()) {
+    res.headers_mut().insert("x-upstream", addr);
+  }
+  res.headers_mut().insert("x-n", HeaderValue::from(n));
+  if let Some(status) = req
+    .headers()
+    .get("x-status")
+    .and_then(|v| StatusCode::from_bytes(v.as_bytes()).ok())
+  {
+    *res.status_mut() = status;
+  }
+  res
+}
+
+/// 在随机端口启动回显上游服务
+pub async fn upstream() -> anyhow::Result<SocketAddr> {
+  upstream_on("127.0.0.1:0".parse()?).await
+}
+
+/// 在指定地址启动回显上游服务
+pub async fn upstream_on(addr: SocketAddr) -> anyhow::Result<SocketAddr> {
+  let listener = tokio::net::TcpListener::bind(addr).await?;
+  let addr = listener.local_addr()?;
+  let n = Arc::new(AtomicU64::new(0));
+  let app = Router::new().fallback(move |req| echo(addr, n.clone(), req));
+  tokio::spawn(async move { axum::serve(listener, app).await });
+  Ok(addr)
+}
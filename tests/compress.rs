@@ -0,0 +1,79 @@
+mod comm;
+
+use std::sync::Arc;
+
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+use gway::{Compress, Encoding};
+use http_body_util::{BodyExt, Full};
+use hyper::{HeaderMap, Request, body::Bytes};
+use tokio::io::AsyncReadExt;
+
+#[test]
+fn test_negotiate() {
+  let compress = Compress::default();
+  assert_eq!(
+    compress.negotiate("gzip, deflate, br, zstd"),
+    Some(Encoding::Zstd)
+  );
+  assert_eq!(
+    compress.negotiate("gzip;q=1, br;q=0.5"),
+    Some(Encoding::Gzip)
+  );
+  assert_eq!(compress.negotiate("zstd;q=0, *"), Some(Encoding::Br));
+  assert_eq!(compress.negotiate("identity"), None);
+}
+
+#[tokio::test]
+async fn test_compress() -> anyhow::Result<()> {
+  let addr = comm::upstream().await?;
+  let route = comm::route(addr);
+  route.with_site("a.test", |conf| {
+    conf.compress = Some(Arc::new(Compress::default()));
+  });
+  let route = Arc::new(route);
+
+  let long = format!("/{}", "x".repeat(2000));
+  let get =
+    async |path: &str, content_type: &str, accept: &str| -> anyhow::Result<(HeaderMap, Bytes)> {
+      let req = Request::builder()
+        .uri(path)
+        .header("host", "a.test")
+        .header("accept-encoding", accept)
+        .header("x-set-content-type", content_type)
+        .header("x-set-etag", "\"v1\"")
+        .body(Full::new(Bytes::new()))?;
+      let res = gway::proxy(req, route.clone()).await;
+      let headers = res.headers().clone();
+      Ok((headers, res.into_body().collect().await?.to_bytes()))
+    };
+  let plain = format!("a.test{long}");
+
+  let (headers, body) = get(&long, "text/html", "gzip").await?;
+  assert_eq!(headers["content-encoding"], "gzip");
+  assert_eq!(headers["vary"], "Accept-Encoding");
+  assert_eq!(headers["etag"], "W/\"v1\"");
+  assert!(!headers.contains_key("content-length"));
+  let mut out = String::new();
+  GzipDecoder::new(&body[..]).read_to_string(&mut out).await?;
+  assert_eq!(out, plain);
+
+  let (headers, body) = get(&long, "application/json", "br, zstd").await?;
+  assert_eq!(headers["content-encoding"], "zstd");
+  let mut out = String::new();
+  ZstdDecoder::new(&body[..]).read_to_string(&mut out).await?;
+  assert_eq!(out, plain);
+
+  // 客户端不接受时不压缩, 但仍需 Vary
+  let (headers, body) = get(&long, "text/html", "identity").await?;
+  assert!(!headers.contains_key("content-encoding"));
+  assert_eq!(headers["vary"], "Accept-Encoding");
+  assert_eq!(body, plain);
+
+  // 太小或类型不符时不压缩
+  let (headers, _) = get("/small", "text/html", "gzip").await?;
+  assert!(!headers.contains_key("content-encoding"));
+  let (headers, _) = get(&long, "image/png", "gzip").await?;
+  assert!(!headers.contains_key("content-encoding"));
+  assert!(!headers.contains_key("vary"));
+  Ok(())
+}
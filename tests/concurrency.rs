@@ -0,0 +1,135 @@
+mod comm;
+
+use std::{sync::Arc, time::Duration};
+
+use gway::{Concurrency, Error, Upstream};
+use http_body_util::Full;
+use hyper::{Request, StatusCode, body::Bytes};
+
+#[tokio::test]
+async fn test_concurrency_limit() -> anyhow::Result<()> {
+  let mut c = Concurrency::new(2, 1, 4);
+  c.max_queue = 1;
+  c.queue_timeout = Duration::from_millis(100);
+  let c = Arc::new(c);
+
+  let p1 = c.acquire().await?;
+  let p2 = c.acquire().await?;
+  assert_eq!(c.in_flight(), 2);
+
+  // 排队等到释放
+  let waiter = tokio::spawn({
+    let c = c.clone();
+    async move { c.acquire().await.map(|p| p.done(true)) }
+  });
+  tokio::time::sleep(Duration::from_millis(20)).await;
+  assert_eq!(c.queue(), 1);
+  // 队列已满
+  assert!(matches!(c.acquire().await, Err(Error::Overloaded)));
+  p1.done(true);
+  waiter.await??;
+  assert_eq!(c.queue(), 0);
+
+  // 等待超时
+  let p3 = c.acquire().await?;
+  assert!(matches!(c.acquire().await, Err(Error::Overloaded)));
+  assert_eq!(c.queue(), 0);
+  drop(p3);
+  drop(p2);
+
+  // 出错时收紧, 延迟正常时放宽
+  for _ in 0..20 {
+    c.acquire().await?.done(false);
+  }
+  assert_eq!(c.limit(), 1);
+  for _ in 0..20 {
+    c.acquire().await?.done(true);
+  }
+  assert!(c.limit() > 1);
+  Ok(())
+}
+
+#[tokio::test]
+async fn test_concurrency_grow() -> anyhow::Result<()> {
+  let mut c = Concurrency::new(1, 1, 4);
+  c.queue_timeout = Duration::from_millis(500);
+  let c = Arc::new(c);
+
+  let p1 = c.acquire().await?;
+  let (tx, _) = tokio::sync::broadcast::channel::<()>(1);
+  let mut task_li = Vec::new();
+  for _ in 0..2 {
+    let c = c.clone();
+    let mut rx = tx.subscribe();
+    task_li.push(tokio::spawn(async move {
+      let permit = c.acquire().await?;
+      rx.recv().await.ok();
+      permit.done(true);
+      anyhow::Ok(())
+    }));
+  }
+  tokio::time::sleep(Duration::from_millis(20)).await;
+  assert_eq!(c.queue(), 2);
+  // 上限从 1 升到 2, 两个排队的请求都能拿到许可
+  p1.done(true);
+  tokio::time::sleep(Duration::from_millis(20)).await;
+  assert_eq!(c.limit(), 2);
+  assert_eq!(c.in_flight(), 2);
+  tx.send(())?;
+  for task in task_li {
+    task.await??;
+  }
+  Ok(())
+}
+
+#[tokio::test]
+async fn test_concurrency_bound() -> anyhow::Result<()> {
+  // 上限小于下限时按下限, 调整时不越界
+  for (initial, min_limit, max_limit) in [(1, 5, 2), (1, 1, 0)] {
+    let c = Concurrency::new(initial, min_limit, max_limit);
+    assert_eq!(c.limit(), min_limit);
+    for ok in [true, false] {
+      c.acquire().await?.done(ok);
+    }
+    assert_eq!(c.limit(), min_limit);
+  }
+  Ok(())
+}
+
+#[tokio::test]
+async fn test_concurrency_shed() -> anyhow::Result<()> {
+  let addr = comm::upstream().await?;
+  let mut concurrency = Concurrency::new(1, 1, 1);
+  concurrency.max_queue = 1;
+  let route = comm::route_to(Upstream {
+    request_timeout_sec: 5,
+    concurrency: Some(concurrency),
+    ..comm::up(addr)
+  });
+  let route = Arc::new(route);
+
+  let mut task_li = Vec::new();
+  for _ in 0..3 {
+    let req = Request::builder()
+      .uri("/")
+      .header("host", "a.test")
+      .header("x-sleep-ms", "300")
+      .body(Full::new(Bytes::new()))?;
+    task_li.push(tokio::spawn(gway::proxy(req, route.clone())));
+    tokio::time::sleep(Duration::from_millis(20)).await;
+  }
+  let mut status_li = Vec::new();
+  for task in task_li {
+    status_li.push(task.await?.status());
+  }
+  // 一个在请求, 一个排队, 一个被拒绝
+  assert_eq!(
+    status_li,
+    [
+      StatusCode::OK,
+      StatusCode::OK,
+      StatusCode::SERVICE_UNAVAILABLE
+    ]
+  );
+  Ok(())
+}
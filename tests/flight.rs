@@ -0,0 +1,206 @@
+mod comm;
+
+use std::{
+  net::SocketAddr,
+  sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering::Relaxed},
+  },
+  time::Duration,
+};
+
+use gway::{CacheRule, Route, Upstream};
+use http_body_util::{BodyExt, Full};
+use hyper::{Request, body::Bytes};
+use tokio::{
+  io::{AsyncReadExt, AsyncWriteExt},
+  net::TcpListener,
+};
+
+fn route(addr: SocketAddr, flight_timeout_sec: u64, max_body: u64) -> Arc<Route> {
+  let route = comm::route_to(Upstream {
+    request_timeout_sec: 5,
+    ..comm::up(addr)
+  });
+  route.with_site("a.test", |conf| {
+    conf.cache = Some(Arc::new(CacheRule {
+      flight_timeout_sec,
+      max_body,
+      ..Default::default()
+    }));
+  });
+  Arc::new(route)
+}
+
+// 分块返回响应体的上游, 每块之间等待 300 毫秒, abort 时发完后直接断开, 不发结束块
+// 返回地址和收到的请求数
+async fn chunked(
+  chunk_li: &'static [&'static str],
+  abort: bool,
+) -> anyhow::Result<(SocketAddr, Arc<AtomicUsize>)> {
+  let listener = TcpListener::bind("127.0.0.1:0").await?;
+  let addr = listener.local_addr()?;
+  let n = Arc::new(AtomicUsize::new(0));
+  let count = n.clone();
+  tokio::spawn(async move {
+    while let Ok((mut stream, _)) = listener.accept().await {
+      count.fetch_add(1, Relaxed);
+      tokio::spawn(async move {
+        let mut buf = Vec::new();
+        let mut b = [0u8; 1024];
+        while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+          let n = stream.read(&mut b).await?;
+          if n == 0 {
+            return anyhow::Ok(());
+          }
+          buf.extend_from_slice(&b[..n]);
+        }
+        stream
+          .write_all(
+            b"HTTP/1.1 200 OK\r\ncache-control: max-age=60\r\ntransfer-encoding: chunked\r\n\r\n",
+          )
+          .await?;
+        for chunk in chunk_li {
+          stream
+            .write_all(format!("{:x}\r\n{chunk}\r\n", chunk.len()).as_bytes())
+            .await?;
+          tokio::time::sleep(Duration::from_millis(300)).await;
+        }
+        if !abort {
+          stream.write_all(b"0\r\n\r\n").await?;
+        }
+        anyhow::Ok(())
+      });
+    }
+  });
+  Ok((addr, n))
+}
+
+// 并发发出 count 个相同的请求, 返回每个响应体, 读取出错时为 None
+async fn concurrent(route: &Arc<Route>, count: usize) -> anyhow::Result<Vec<Option<Bytes>>> {
+  let mut task_li = Vec::new();
+  for _ in 0..count {
+    let req = Request::builder()
+      .uri("/stream")
+      .header("host", "a.test")
+      .body(Full::new(Bytes::new()))?;
+    let route = route.clone();
+    task_li.push(tokio::spawn(async move {
+      let res = gway::proxy(req, route).await;
+      res
+        .into_body()
+        .collect()
+        .await
+        .ok()
+        .map(|body| body.to_bytes())
+    }));
+  }
+  let mut out = Vec::new();
+  for task in task_li {
+    out.push(task.await?);
+  }
+  Ok(out)
+}
+
+// 并发发出 count 个请求, 上游响应 cache_control, 返回每个响应的 x-n 和响应体
+async fn burst(
+  route: &Arc<Route>,
+  path: &str,
+  cache_control: &str,
+  count: usize,
+  sleep_ms: u64,
+  accept_encoding: impl Fn(usize) -> &'static str,
+) -> anyhow::Result<Vec<(String, Bytes)>> {
+  let mut task_li = Vec::new();
+  for i in 0..count {
+    let req = Request::builder()
+      .uri(path)
+      .header("host", "a.test")
+      .header("accept-encoding", accept_encoding(i))
+      .header("x-set-cache-control", cache_control)
+      .header("x-sleep-ms", sleep_ms.to_string())
+      .body(Full::new(Bytes::new()))?;
+    let route = route.clone();
+    task_li.push(tokio::spawn(async move {
+      let res = gway::proxy(req, route).await;
+      let n = res.headers()["x-n"].to_str()?.to_owned();
+      anyhow::Ok((n, res.into_body().collect().await?.to_bytes()))
+    }));
+  }
+  let mut out = Vec::new();
+  for task in task_li {
+    out.push(task.await??);
+  }
+  Ok(out)
+}
+
+#[tokio::test]
+async fn test_flight() -> anyhow::Result<()> {
+  let route = route(comm::upstream().await?, 10, 1 << 20);
+  let li = burst(&route, "/page", "max-age=60", 10, 300, |_| "gzip").await?;
+  assert!(li.iter().all(|(n, body)| *n == li[0].0 && *body == li[0].1));
+  assert_eq!(li[0].1, "a.test/page");
+
+  // 请求头不同的不合并
+  let li = burst(&route, "/lang", "max-age=60", 2, 300, |i| {
+    if i == 0 { "gzip" } else { "br" }
+  })
+  .await?;
+  assert_ne!(li[0].0, li[1].0);
+
+  // 不可缓存的响应不共享
+  for cache_control in ["no-store", "private"] {
+    let li = burst(&route, "/private", cache_control, 3, 300, |_| "gzip").await?;
+    let mut n_li: Vec<_> = li.into_iter().map(|(n, _)| n).collect();
+    n_li.sort();
+    n_li.dedup();
+    assert_eq!(n_li.len(), 3);
+  }
+  Ok(())
+}
+
+#[tokio::test]
+async fn test_flight_timeout() -> anyhow::Result<()> {
+  let route = route(comm::upstream().await?, 1, 1 << 20);
+  let li = burst(&route, "/page", "max-age=60", 3, 1500, |_| "gzip").await?;
+  let mut n_li: Vec<_> = li.into_iter().map(|(n, _)| n).collect();
+  n_li.sort();
+  n_li.dedup();
+  assert_eq!(n_li.len(), 3);
+  Ok(())
+}
+
+#[tokio::test]
+async fn test_flight_max_body() -> anyhow::Result<()> {
+  // 响应体超过 max_body 时不缓冲给跟随者
+  let route = route(comm::upstream().await?, 10, 4);
+  let li = burst(&route, "/page", "max-age=60", 3, 300, |_| "gzip").await?;
+  let mut n_li: Vec<_> = li.into_iter().map(|(n, _)| n).collect();
+  n_li.sort();
+  n_li.dedup();
+  assert_eq!(n_li.len(), 3);
+  Ok(())
+}
+
+#[tokio::test]
+async fn test_flight_chunked_over() -> anyhow::Result<()> {
+  // 分块响应读到一半超过 max_body, 跟随者以错误结束, 不能拿到截断的响应体
+  let (addr, n) = chunked(&["aaaa", "bbbb"], false).await?;
+  let route = route(addr, 10, 6);
+  let li = concurrent(&route, 3).await?;
+  assert_eq!(n.load(Relaxed), 1);
+  let ok_li: Vec<_> = li.into_iter().flatten().collect();
+  assert_eq!(ok_li, [Bytes::from("aaaabbbb")]);
+  Ok(())
+}
+
+#[tokio::test]
+async fn test_flight_upstream_error() -> anyhow::Result<()> {
+  // 上游响应体中途出错, 领头请求和跟随者都以错误结束
+  let (addr, n) = chunked(&["aaaa", "bbbb"], true).await?;
+  let route = route(addr, 10, 1 << 20);
+  let li = concurrent(&route, 3).await?;
+  assert_eq!(n.load(Relaxed), 1);
+  assert!(li.iter().all(Option::is_none), "{li:?}");
+  Ok(())
+}
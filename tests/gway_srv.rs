@@ -69,6 +69,7 @@ extern "C" fn start_server() {
         request_timeout_sec: 10,
         max_retry: 3,
         protocol: Protocol::H1,
+        ..Default::default()
       };
       let site_conf = SiteConf::new(Arc::new(upstream), host.into());
       route.host_conf.insert(host.into(), site_conf);
@@ -0,0 +1,65 @@
+mod comm;
+
+use std::sync::Arc;
+
+use gway::{HeaderOp, HeaderRule, Peer};
+use http_body_util::Full;
+use hyper::{Request, body::Bytes, header::HeaderName};
+
+#[tokio::test]
+async fn test_header_rule() -> anyhow::Result<()> {
+  let addr = comm::upstream().await?;
+  let route = comm::route(addr);
+  let name = HeaderName::from_static;
+  route.with_site("a.test", |conf| {
+    conf.header_rule_li = [
+      HeaderRule::req(name("x-site-id"), HeaderOp::Set("{host}".parse().unwrap())),
+      HeaderRule::req(
+        name("x-real-ip"),
+        HeaderOp::Set("{client_ip}".parse().unwrap()),
+      ),
+      HeaderRule::req(
+        name("x-request-id"),
+        HeaderOp::Default("{request_id}".parse().unwrap()),
+      ),
+      HeaderRule::req(name("x-debug"), HeaderOp::Remove),
+      HeaderRule::res(name("x-upstream"), HeaderOp::Remove),
+      HeaderRule::res(
+        name("cache-control"),
+        HeaderOp::Set("max-age=3600".parse().unwrap()),
+      )
+      .path("/static/"),
+    ]
+    .into();
+  });
+  let route = Arc::new(route);
+
+  let get = async |path: &str, id: Option<&str>| -> anyhow::Result<hyper::HeaderMap> {
+    let mut req = Request::builder()
+      .uri(path)
+      .header("host", "a.test")
+      .header("x-debug", "1");
+    if let Some(id) = id {
+      req = req.header("x-request-id", id);
+    }
+    let mut req = req.body(Full::new(Bytes::new()))?;
+    req
+      .extensions_mut()
+      .insert(Peer::new("10.0.0.1:1234".parse()?, false));
+    let res = gway::proxy(req, route.clone()).await;
+    Ok(res.headers().clone())
+  };
+
+  let headers = get("/static/a.js", None).await?;
+  assert_eq!(headers["x-req-x-site-id"], "a.test");
+  assert_eq!(headers["x-req-x-real-ip"], "10.0.0.1");
+  assert_eq!(headers["x-req-x-request-id"].len(), 16);
+  assert!(!headers.contains_key("x-req-x-debug"));
+  assert!(!headers.contains_key("x-upstream"));
+  assert_eq!(headers["cache-control"], "max-age=3600");
+
+  let headers = get("/api", Some("abc")).await?;
+  assert_eq!(headers["x-req-x-request-id"], "abc");
+  assert!(!headers.contains_key("cache-control"));
+  Ok(())
+}
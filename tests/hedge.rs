@@ -0,0 +1,86 @@
+mod comm;
+
+use std::{
+  sync::Arc,
+  time::{Duration, Instant},
+};
+
+use gway::{Hedge, PathMatch, Rule, Upstream};
+use http_body_util::{BodyExt, Full};
+use hyper::{Method, Request, StatusCode, body::Bytes};
+
+#[test]
+fn test_hedge_delay() {
+  let mut hedge = Hedge::new(0.5);
+  hedge.max_delay = Duration::from_secs(10);
+  for ms in 1..=64 {
+    hedge.record(Duration::from_millis(ms));
+  }
+  assert_eq!(hedge.delay(), Duration::from_millis(33));
+  assert!(hedge.is_hedgeable(&Method::GET));
+  assert!(!hedge.is_hedgeable(&Method::POST));
+}
+
+#[tokio::test]
+async fn test_hedge_race_latency() {
+  // 对冲请求胜出时, 延迟从发出第一个请求算起
+  let mut hedge = Hedge::new(0.5);
+  hedge.max_delay = Duration::from_millis(20);
+  for _ in 0..32 {
+    let r = hedge
+      .race(0, 1, |pos| async move {
+        if pos == 0 {
+          tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        Ok::<_, ()>(pos)
+      })
+      .await;
+    assert_eq!(r, (1, Ok(1)));
+  }
+  assert_eq!(hedge.delay(), hedge.max_delay);
+}
+
+#[tokio::test]
+async fn test_hedge() -> anyhow::Result<()> {
+  let fast = comm::upstream().await?;
+  // 总是很慢的后端
+  let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+  let slow = listener.local_addr()?;
+  tokio::spawn(async move {
+    let app = axum::Router::new().fallback(|| async {
+      tokio::time::sleep(Duration::from_millis(1000)).await;
+      "slow"
+    });
+    axum::serve(listener, app).await
+  });
+
+  let route = comm::route_to(Upstream {
+    addr_li: vec![slow, fast].into(),
+    request_timeout_sec: 5,
+    ..comm::up(fast)
+  });
+  let upstream = route.upstream("up").ok_or(anyhow::anyhow!("no upstream"))?;
+  let mut hedge = Hedge::new(0.95);
+  hedge.max_delay = Duration::from_millis(50);
+  let mut rule = Rule::new(PathMatch::Prefix("/read/".into()), upstream);
+  rule.hedge = Some(Arc::new(hedge));
+  route.with_site("a.test", |conf| {
+    conf.rule_li = Arc::new([rule]);
+  });
+  let route = Arc::new(route);
+
+  // 轮询时一半请求先发到慢的后端, 对冲后都由快的后端响应
+  for _ in 0..4 {
+    let start = Instant::now();
+    let req = Request::builder()
+      .uri("/read/x")
+      .header("host", "a.test")
+      .body(Full::new(Bytes::new()))?;
+    let res = gway::proxy(req, route.clone()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["x-upstream"], fast.to_string());
+    res.into_body().collect().await?;
+    assert!(start.elapsed() < Duration::from_millis(800));
+  }
+  Ok(())
+}
@@ -0,0 +1,49 @@
+mod comm;
+
+use std::sync::Arc;
+
+use gway::normalize_host;
+use http_body_util::{BodyExt, Full};
+use hyper::{Request, StatusCode, body::Bytes};
+
+#[test]
+fn test_normalize_host() {
+  assert_eq!(normalize_host("Example.COM"), "example.com");
+  assert_eq!(normalize_host("example.com:443"), "example.com");
+  assert_eq!(normalize_host("example.com."), "example.com");
+  assert_eq!(normalize_host("Example.com.:8443"), "example.com");
+  assert_eq!(normalize_host("[::1]:443"), "[::1]");
+  assert_eq!(normalize_host("127.0.0.1:80"), "127.0.0.1");
+  assert_eq!(normalize_host("例子.测试"), "xn--fsqu00a.xn--0zwm56d");
+  assert_eq!(normalize_host("Bücher.DE:443"), "xn--bcher-kva.de");
+  assert_eq!(normalize_host("*.Example.com"), "*.example.com");
+}
+
+#[tokio::test]
+async fn test_host_route() -> anyhow::Result<()> {
+  let addr = comm::upstream().await?;
+  let mut route = comm::route(addr);
+  route.set("Bücher.de", "bücher.de", "up");
+  let route = Arc::new(route);
+
+  for host in ["bücher.de", "BÜCHER.de.", "xn--bcher-kva.de:443"] {
+    let req = Request::builder()
+      .uri("/")
+      .header("host", host)
+      .body(Full::new(Bytes::new()))?;
+    let res = gway::proxy(req, route.clone()).await;
+    assert_eq!(res.status(), StatusCode::OK, "{host}");
+    let body = res.into_body().collect().await?.to_bytes();
+    assert!(body.ends_with(b"/"));
+  }
+
+  // 子域名跳转使用规范化后的域名
+  let req = Request::builder()
+    .uri("/a")
+    .header("host", "WWW.Bücher.de:443")
+    .body(Full::new(Bytes::new()))?;
+  let res = gway::proxy(req, route.clone()).await;
+  assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+  assert_eq!(res.headers()["location"], "https://xn--bcher-kva.de/a");
+  Ok(())
+}
@@ -0,0 +1,124 @@
+mod comm;
+
+use std::{collections::HashMap, sync::Arc};
+
+use gway::{Locale, LocaleAction, LocaleBy, LocaleRoute, PathMatch, Rule};
+use http_body_util::{BodyExt, Full};
+use hyper::{HeaderMap, Method, Request, StatusCode, body::Bytes};
+
+fn headers(li: &[(&'static str, &'static str)]) -> HeaderMap {
+  li.iter()
+    .map(|(k, v)| (k.parse().unwrap(), v.parse().unwrap()))
+    .collect()
+}
+
+#[test]
+fn test_negotiate() {
+  let mut locale = Locale::new(
+    ["en".into(), "zh-CN".into(), "ja".into()],
+    LocaleBy::Path,
+    LocaleAction::Redirect(StatusCode::FOUND),
+  );
+  locale.cookie = Some("lang".into());
+
+  let lang = |li| locale.negotiate(&headers(li)).unwrap();
+  assert_eq!(lang(&[]), "en");
+  assert_eq!(
+    lang(&[("accept-language", "ja;q=0.5, zh-TW;q=0.9, fr")]),
+    "zh-CN"
+  );
+  assert_eq!(lang(&[("accept-language", "fr, *;q=0.5")]), "en");
+  assert_eq!(lang(&[("accept-language", "JA")]), "ja");
+  assert_eq!(lang(&[("accept-language", "ja;q=0, zh")]), "zh-CN");
+  assert_eq!(
+    lang(&[("accept-language", "ja"), ("cookie", "a=1; lang=en")]),
+    "en"
+  );
+
+  let h = headers(&[("accept-language", "ja")]);
+  assert_eq!(
+    locale.route(&Method::GET, "a.test", "/doc?x=1", &h),
+    Some(LocaleRoute::Redirect {
+      lang: "ja".into(),
+      status: StatusCode::FOUND,
+      location: "/ja/doc?x=1".into()
+    })
+  );
+  assert_eq!(
+    locale.route(&Method::GET, "a.test", "/zh-CN/doc", &h),
+    Some(LocaleRoute::Has("zh-CN".into()))
+  );
+  assert_eq!(locale.route(&Method::POST, "a.test", "/doc", &h), None);
+}
+
+#[tokio::test]
+async fn test_locale_proxy() -> anyhow::Result<()> {
+  let addr = comm::upstream().await?;
+  let mut route = comm::route(addr);
+  let api = comm::upstream().await?;
+  route.add_upstream("api", comm::up(api));
+  let api_up = route.upstream("api").ok_or(anyhow::anyhow!("no api"))?;
+  route.set("path.test", "path.test", "up");
+  route.set("host.test", "host.test", "up");
+  route.with_site("path.test", |conf| {
+    let mut strip = Rule::new(PathMatch::Prefix("/api/".into()), api_up.clone());
+    strip.strip = Some("/api".into());
+    conf.rule_li = [Rule::new(PathMatch::Exact("/api".into()), api_up), strip].into();
+    conf.locale = Some(Arc::new(Locale::new(
+      ["en".into(), "zh".into()],
+      LocaleBy::Path,
+      LocaleAction::Rewrite,
+    )))
+  });
+  route.with_site("host.test", |conf| {
+    conf.locale = Some(Arc::new(Locale::new(
+      ["en".into(), "zh".into()],
+      LocaleBy::Host(HashMap::from([
+        ("en".into(), "en.host.test".into()),
+        ("zh".into(), "zh.host.test".into()),
+      ])),
+      LocaleAction::Redirect(StatusCode::FOUND),
+    )))
+  });
+  let route = Arc::new(route);
+
+  let get = |host: &str, path: &str| {
+    Request::builder()
+      .uri(path)
+      .header("host", host)
+      .header("accept-language", "zh-CN,zh;q=0.9,en;q=0.8")
+      .body(Full::new(Bytes::new()))
+  };
+
+  // 路径模式内部改写
+  let res = gway::proxy(get("path.test", "/doc")?, route.clone()).await;
+  assert_eq!(res.status(), StatusCode::OK);
+  assert_eq!(res.headers()["vary"], "Accept-Language");
+  assert_eq!(res.headers()["content-language"], "zh");
+  let body = res.into_body().collect().await?.to_bytes();
+  assert_eq!(body, "path.test/zh/doc");
+
+  // 规则按改写前的路径匹配
+  let res = gway::proxy(get("path.test", "/api")?, route.clone()).await;
+  assert_eq!(res.headers()["x-upstream"], api.to_string());
+  let body = res.into_body().collect().await?.to_bytes();
+  assert_eq!(body, "path.test/zh/api");
+
+  // 去前缀作用于客户端路径, 再补回语言前缀
+  let res = gway::proxy(get("path.test", "/api/x?q=1")?, route.clone()).await;
+  assert_eq!(res.headers()["x-upstream"], api.to_string());
+  let body = res.into_body().collect().await?.to_bytes();
+  assert_eq!(body, "path.test/zh/x?q=1");
+
+  // 已带语言前缀
+  let res = gway::proxy(get("path.test", "/en/doc")?, route.clone()).await;
+  assert_eq!(res.headers()["content-language"], "en");
+  assert!(res.headers().get("vary").is_none());
+
+  // 域名模式跳转
+  let res = gway::proxy(get("host.test", "/doc")?, route.clone()).await;
+  assert_eq!(res.status(), StatusCode::FOUND);
+  assert_eq!(res.headers()["location"], "https://zh.host.test/doc");
+  assert_eq!(res.headers()["vary"], "Accept-Language");
+  Ok(())
+}
@@ -0,0 +1,117 @@
+mod comm;
+
+use std::{sync::Arc, time::Duration};
+
+use gway::{CacheRule, Mirror, Route, Upstream};
+use http_body_util::{BodyExt, Full};
+use hyper::{Request, StatusCode, body::Bytes};
+
+fn route(primary: std::net::SocketAddr, shadow: std::net::SocketAddr) -> Route {
+  let mut route = comm::route_to(Upstream {
+    request_timeout_sec: 5,
+    ..comm::up(primary)
+  });
+  route.add_upstream(
+    "shadow",
+    Upstream {
+      request_timeout_sec: 5,
+      ..comm::up(shadow)
+    },
+  );
+  route
+}
+
+async fn wait_shadow(mirror: &Mirror, n: u64) {
+  for _ in 0..50 {
+    if mirror.shadow.count() == n {
+      break;
+    }
+    tokio::time::sleep(Duration::from_millis(20)).await;
+  }
+}
+
+#[tokio::test]
+async fn test_mirror() -> anyhow::Result<()> {
+  let primary = comm::upstream().await?;
+  let shadow = comm::upstream().await?;
+  let route = route(primary, shadow);
+  let shadow_up = route
+    .upstream("shadow")
+    .ok_or(anyhow::anyhow!("no upstream"))?;
+  let mirror = Arc::new(Mirror::new(shadow_up, 1.0));
+  route.with_site("a.test", |conf| {
+    conf.mirror = Some(mirror.clone());
+  });
+  let route = Arc::new(route);
+
+  let send = async |status: &str| -> anyhow::Result<(StatusCode, Bytes)> {
+    let req = Request::builder()
+      .method("POST")
+      .uri("/m")
+      .header("host", "a.test")
+      .header("x-status", status)
+      .body(Full::new(Bytes::from("data")))?;
+    let res = gway::proxy(req, route.clone()).await;
+    // 客户端只看到主上游的响应
+    assert_eq!(res.headers()["x-upstream"], primary.to_string());
+    Ok((res.status(), res.into_body().collect().await?.to_bytes()))
+  };
+  assert_eq!(
+    send("200").await?,
+    (StatusCode::OK, Bytes::from("a.test/m"))
+  );
+  assert_eq!(send("503").await?.0, StatusCode::SERVICE_UNAVAILABLE);
+
+  // 等待影子请求完成
+  wait_shadow(&mirror, 2).await;
+  for stat in [&mirror.primary, &mirror.shadow] {
+    assert_eq!(stat.count(), 2);
+    assert_eq!(stat.status(2), 1);
+    assert_eq!(stat.status(5), 1);
+    assert_eq!(stat.error(), 0);
+  }
+
+  // 比例为 0 时不镜像
+  let mirror = Arc::new(Mirror::new(mirror.upstream.clone(), 0.0));
+  route.with_site("a.test", |conf| {
+    conf.mirror = Some(mirror.clone());
+  });
+  send("200").await?;
+  tokio::time::sleep(Duration::from_millis(100)).await;
+  assert_eq!(mirror.shadow.count(), 0);
+  assert_eq!(mirror.primary.count(), 0);
+  Ok(())
+}
+
+#[tokio::test]
+async fn test_mirror_cache_hit() -> anyhow::Result<()> {
+  let primary = comm::upstream().await?;
+  let shadow = comm::upstream().await?;
+  let route = route(primary, shadow);
+  let shadow_up = route
+    .upstream("shadow")
+    .ok_or(anyhow::anyhow!("no upstream"))?;
+  let mirror = Arc::new(Mirror::new(shadow_up, 1.0));
+  route.with_site("a.test", |conf| {
+    conf.cache = Some(Arc::new(CacheRule::default()));
+    conf.mirror = Some(mirror.clone());
+  });
+  let route = Arc::new(route);
+
+  for _ in 0..2 {
+    let req = Request::builder()
+      .uri("/c")
+      .header("host", "a.test")
+      .header("x-set-cache-control", "max-age=60")
+      .body(Full::new(Bytes::new()))?;
+    let res = gway::proxy(req, route.clone()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    res.into_body().collect().await?;
+  }
+  // 命中缓存的请求没有发往主上游, 不镜像也不计入统计
+  wait_shadow(&mirror, 1).await;
+  tokio::time::sleep(Duration::from_millis(100)).await;
+  assert_eq!(mirror.primary.count(), 1);
+  assert_eq!(mirror.shadow.count(), 1);
+  Ok(())
+}
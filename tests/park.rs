@@ -0,0 +1,63 @@
+mod comm;
+
+use std::{sync::Arc, time::Duration};
+
+use gway::{Concurrency, Park, Route, Upstream};
+use http_body_util::Full;
+use hyper::{Request, StatusCode, body::Bytes};
+
+async fn route(park_ms: u64) -> anyhow::Result<(Arc<Route>, std::net::SocketAddr)> {
+  // 先占用再释放端口, 模拟正在重启拒绝连接的后端
+  let addr = tokio::net::TcpListener::bind("127.0.0.1:0")
+    .await?
+    .local_addr()?;
+  let route = comm::route_to(Upstream {
+    max_retry: 1,
+    park: Some(Arc::new(Park::new(Duration::from_millis(park_ms)))),
+    concurrency: Some(Concurrency::new(1, 1, 1)),
+    ..comm::up(addr)
+  });
+  Ok((Arc::new(route), addr))
+}
+
+fn req() -> anyhow::Result<Request<Full<Bytes>>> {
+  Ok(
+    Request::builder()
+      .uri("/park")
+      .header("host", "a.test")
+      .body(Full::new(Bytes::new()))?,
+  )
+}
+
+#[tokio::test]
+async fn test_park() -> anyhow::Result<()> {
+  let (route, addr) = route(3000).await?;
+  let task_li: Vec<_> = (0..3)
+    .map(|_| Ok(tokio::spawn(gway::proxy(req()?, route.clone()))))
+    .collect::<anyhow::Result<_>>()?;
+  // 后端恢复后, 等待中的请求都能完成
+  tokio::time::sleep(Duration::from_millis(500)).await;
+  // 等待恢复时不占用并发许可
+  let upstream = route.upstream("up").ok_or(anyhow::anyhow!("no up"))?;
+  let concurrency = upstream
+    .concurrency
+    .as_ref()
+    .ok_or(anyhow::anyhow!("no concurrency"))?;
+  assert_eq!(concurrency.in_flight(), 0);
+  assert_eq!(concurrency.queue(), 0);
+  comm::upstream_on(addr).await?;
+  for task in task_li {
+    assert_eq!(task.await?.status(), StatusCode::OK);
+  }
+  Ok(())
+}
+
+#[tokio::test]
+async fn test_park_timeout() -> anyhow::Result<()> {
+  let (route, _) = route(300).await?;
+  let start = std::time::Instant::now();
+  let res = gway::proxy(req()?, route).await;
+  assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+  assert!(start.elapsed() >= Duration::from_millis(300));
+  Ok(())
+}
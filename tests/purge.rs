@@ -0,0 +1,115 @@
+mod comm;
+
+use std::sync::Arc;
+
+use gway::{Admin, CacheRule, Purge};
+use http_body_util::{BodyExt, Full};
+use hyper::{HeaderMap, Request, StatusCode, body::Bytes};
+
+#[tokio::test]
+async fn test_purge() -> anyhow::Result<()> {
+  let addr = comm::upstream().await?;
+  let mut route = comm::route(addr);
+  for host in ["a.test", "b.test"] {
+    route.set(host, host, "up");
+    route.with_site(host, |conf| {
+      conf.cache = Some(Arc::new(CacheRule::default()));
+    });
+  }
+  route.admin = Some(Admin::new("admin.test", "secret"));
+  let route = Arc::new(route);
+
+  let req = async |method: &str,
+                   host: &str,
+                   path: &str,
+                   header_li: &[(&str, &str)]|
+         -> anyhow::Result<(StatusCode, HeaderMap, Bytes)> {
+    let mut req = Request::builder()
+      .method(method)
+      .uri(path)
+      .header("host", host);
+    for (k, v) in header_li {
+      req = req.header(*k, *v);
+    }
+    let res = gway::proxy(req.body(Full::new(Bytes::new()))?, route.clone()).await;
+    let status = res.status();
+    let headers = res.headers().clone();
+    Ok((status, headers, res.into_body().collect().await?.to_bytes()))
+  };
+  // 返回上游收到的第几个请求
+  let n = async |host: &str, path: &str, tag: &str| -> anyhow::Result<String> {
+    let (_, headers, _) = req(
+      "GET",
+      host,
+      path,
+      &[
+        ("x-set-cache-control", "max-age=60"),
+        ("x-set-surrogate-key", tag),
+      ],
+    )
+    .await?;
+    assert!(!headers.contains_key("surrogate-key"));
+    Ok(headers["x-n"].to_str()?.to_owned())
+  };
+
+  let a1 = n("a.test", "/docs/1", "doc zh").await?;
+  let a2 = n("a.test", "/docs/2", "doc en").await?;
+  let a3 = n("a.test", "/blog", "blog").await?;
+  let b1 = n("b.test", "/docs/1", "doc").await?;
+  assert_eq!(n("a.test", "/docs/1", "doc zh").await?, a1);
+
+  // 按地址
+  assert_eq!(route.cache.purge(Purge::Url("a.test/docs/1".into())), 1);
+  assert_ne!(n("a.test", "/docs/1", "doc zh").await?, a1);
+  assert_eq!(n("a.test", "/docs/2", "doc en").await?, a2);
+
+  // 按标签, 跨域名
+  assert_eq!(route.cache.purge(Purge::Tag("doc".into())), 3);
+  assert_ne!(n("a.test", "/docs/2", "doc en").await?, a2);
+  assert_ne!(n("b.test", "/docs/1", "doc").await?, b1);
+  assert_eq!(n("a.test", "/blog", "blog").await?, a3);
+  n("a.test", "/docs/1", "doc zh").await?;
+
+  // 按路径前缀
+  assert_eq!(route.cache.purge(Purge::Prefix("a.test/docs/".into())), 2);
+  assert_eq!(n("a.test", "/blog", "blog").await?, a3);
+
+  // 按域名
+  let b1 = n("b.test", "/docs/1", "doc").await?;
+  assert_eq!(route.cache.purge(Purge::Host("a.test".into())), 1);
+  assert_ne!(n("a.test", "/blog", "blog").await?, a3);
+  assert_eq!(n("b.test", "/docs/1", "doc").await?, b1);
+
+  // 管理接口
+  let auth = ("authorization", "Bearer secret");
+  let (status, _, _) = req("POST", "admin.test", "/purge?host=b.test", &[]).await?;
+  assert_eq!(status, StatusCode::UNAUTHORIZED);
+  let (status, _, _) = req(
+    "POST",
+    "admin.test",
+    "/purge?host=b.test",
+    &[("authorization", "Bearer wrong!")],
+  )
+  .await?;
+  assert_eq!(status, StatusCode::UNAUTHORIZED);
+  let (status, _, _) = req("GET", "admin.test", "/purge?host=b.test", &[auth]).await?;
+  assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+  let (status, _, _) = req("POST", "admin.test", "/purge", &[auth]).await?;
+  assert_eq!(status, StatusCode::BAD_REQUEST);
+
+  let (status, _, body) = req(
+    "POST",
+    "admin.test",
+    "/purge?url=https%3A%2F%2Fb.test%2Fdocs%2F1",
+    &[auth],
+  )
+  .await?;
+  assert_eq!(status, StatusCode::OK);
+  assert_eq!(body, "1");
+  assert_ne!(n("b.test", "/docs/1", "doc").await?, b1);
+
+  let (_, _, body) = req("POST", "admin.test", "/purge?tag=blog&tag=doc", &[auth]).await?;
+  assert_eq!(body, "2");
+  assert!(route.cache.is_empty());
+  Ok(())
+}
@@ -0,0 +1,67 @@
+mod comm;
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use gway::{LimitBy, PathMatch, Peer, RateLimit};
+use http_body_util::Full;
+use hyper::{Request, StatusCode, body::Bytes};
+
+#[test]
+fn test_token_bucket() {
+  let limit = RateLimit::new(LimitBy::Ip, 1.0, 3);
+  for _ in 0..3 {
+    assert_eq!(limit.take("a".into()), Ok(()));
+  }
+  assert_eq!(limit.take("a".into()), Err(1));
+  // 不同的键互不影响
+  assert_eq!(limit.take("b".into()), Ok(()));
+}
+
+#[tokio::test]
+async fn test_rate_limit() -> anyhow::Result<()> {
+  let addr = comm::upstream().await?;
+  let route = comm::route(addr);
+  let mut api = RateLimit::new(LimitBy::Header("x-api-key".parse()?), 10.0, 1);
+  api.path = PathMatch::Prefix("/api/".into());
+  route.with_site("a.test", |conf| {
+    conf.rate_limit_li = Arc::new([api, RateLimit::new(LimitBy::Ip, 0.5, 2)]);
+  });
+  let route = Arc::new(route);
+
+  let get = async |ip: &str, path: &str, key: Option<&str>| -> anyhow::Result<StatusCode> {
+    let mut req = Request::builder().uri(path).header("host", "a.test");
+    if let Some(key) = key {
+      req = req.header("x-api-key", key);
+    }
+    let mut req = req.body(Full::new(Bytes::new()))?;
+    let peer: SocketAddr = format!("{ip}:1234").parse()?;
+    req.extensions_mut().insert(Peer::new(peer, true));
+    let res = gway::proxy(req, route.clone()).await;
+    if res.status() == StatusCode::TOO_MANY_REQUESTS {
+      assert!(res.headers().contains_key("retry-after"));
+    }
+    Ok(res.status())
+  };
+
+  // 按客户端 IP, 允许突发 2 个
+  assert_eq!(get("10.0.0.1", "/", None).await?, StatusCode::OK);
+  assert_eq!(get("10.0.0.1", "/", None).await?, StatusCode::OK);
+  assert_eq!(
+    get("10.0.0.1", "/", None).await?,
+    StatusCode::TOO_MANY_REQUESTS
+  );
+  assert_eq!(get("10.0.0.2", "/", None).await?, StatusCode::OK);
+
+  // 按 API key, 只统计 /api/ 下的请求
+  assert_eq!(get("10.0.0.3", "/api/a", Some("k1")).await?, StatusCode::OK);
+  assert_eq!(
+    get("10.0.0.4", "/api/a", Some("k1")).await?,
+    StatusCode::TOO_MANY_REQUESTS
+  );
+  assert_eq!(get("10.0.0.4", "/api/a", Some("k2")).await?, StatusCode::OK);
+
+  // 令牌按速率补充
+  tokio::time::sleep(Duration::from_millis(2100)).await;
+  assert_eq!(get("10.0.0.1", "/", None).await?, StatusCode::OK);
+  Ok(())
+}
@@ -0,0 +1,85 @@
+mod comm;
+
+use std::sync::Arc;
+
+use gway::{RedirectMap, RedirectTable};
+use http_body_util::Full;
+use hyper::{Request, StatusCode, body::Bytes};
+
+const RULES: &str = "
+# 注释
+/old/page   /new/page
+/a          https://other.com/b?x=1  302
+/docs/*     /v2/docs/*               308 drop
+/blog/*     /news
+";
+
+#[test]
+fn test_redirect_table() -> anyhow::Result<()> {
+  let table = RedirectTable::parse(RULES)?;
+  assert_eq!(table.len(), 4);
+
+  assert_eq!(
+    table.get("/old/page?q=1"),
+    Some((StatusCode::MOVED_PERMANENTLY, "/new/page?q=1".into()))
+  );
+  assert_eq!(
+    table.get("/a?y=2"),
+    Some((StatusCode::FOUND, "https://other.com/b?x=1&y=2".into()))
+  );
+  assert_eq!(
+    table.get("/docs/guide/intro?lang=en"),
+    Some((
+      StatusCode::PERMANENT_REDIRECT,
+      "/v2/docs/guide/intro".into()
+    ))
+  );
+  assert_eq!(
+    table.get("/blog/2024/post"),
+    Some((StatusCode::MOVED_PERMANENTLY, "/news".into()))
+  );
+  assert_eq!(table.get("/docs"), None);
+  assert_eq!(table.get("/old/page/more"), None);
+
+  assert!(RedirectTable::parse("/a /b 200").is_err());
+  assert!(RedirectTable::parse("a /b").is_err());
+  assert!(RedirectTable::parse("/a* /c").is_err());
+  Ok(())
+}
+
+#[tokio::test]
+async fn test_redirect_reload() -> anyhow::Result<()> {
+  let file = std::env::temp_dir().join(format!("gway_redirect_{}.txt", std::process::id()));
+  tokio::fs::write(&file, "/x /y").await?;
+  let map = RedirectMap::load(&file).await?;
+
+  let mut route = comm::route("127.0.0.1:1".parse()?);
+  route.set("redirect.test", "redirect.test", "up");
+  assert!(route.set_redirect("redirect.test", Some(map.clone())));
+  let route = Arc::new(route);
+
+  let get = |path: &str| {
+    Request::builder()
+      .uri(path)
+      .header("host", "redirect.test")
+      .body(Full::new(Bytes::new()))
+  };
+
+  let res = gway::proxy(get("/x")?, route.clone()).await;
+  assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+  assert_eq!(res.headers()["location"], "/y");
+
+  tokio::fs::write(&file, "/x /z 307").await?;
+  assert_eq!(map.reload().await?, 1);
+  let res = gway::proxy(get("/x")?, route.clone()).await;
+  assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+  assert_eq!(res.headers()["location"], "/z");
+
+  // 解析失败时保留旧规则
+  tokio::fs::write(&file, "/x").await?;
+  assert!(map.reload().await.is_err());
+  assert_eq!(map.get("/x").map(|(_, l)| l), Some("/z".into()));
+
+  tokio::fs::remove_file(&file).await?;
+  Ok(())
+}
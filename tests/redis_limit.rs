@@ -0,0 +1,87 @@
+#![cfg(feature = "redis")]
+
+use std::{
+  process::{Child, Command, Stdio},
+  sync::Arc,
+  time::Duration,
+};
+
+use gway::{LimitBy, RateLimit, RedisStore, Shared};
+
+struct RedisServer(Child);
+
+impl Drop for RedisServer {
+  fn drop(&mut self) {
+    let _ = self.0.kill();
+    let _ = self.0.wait();
+  }
+}
+
+// 在随机端口启动本地 redis-server, 没有安装时返回 None
+async fn redis_server() -> anyhow::Result<Option<(RedisServer, u16)>> {
+  let port = std::net::TcpListener::bind("127.0.0.1:0")?
+    .local_addr()?
+    .port();
+  let Ok(child) = Command::new("redis-server")
+    .args([
+      "--port",
+      &port.to_string(),
+      "--save",
+      "",
+      "--appendonly",
+      "no",
+    ])
+    .stdout(Stdio::null())
+    .spawn()
+  else {
+    eprintln!("redis-server not found, skip");
+    return Ok(None);
+  };
+  let server = RedisServer(child);
+  for _ in 0..50 {
+    if tokio::net::TcpStream::connect(("127.0.0.1", port))
+      .await
+      .is_ok()
+    {
+      return Ok(Some((server, port)));
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+  }
+  anyhow::bail!("redis-server not ready")
+}
+
+fn limit(store: &Arc<RedisStore>) -> RateLimit {
+  let mut limit = RateLimit::new(LimitBy::Ip, 1.0, 3);
+  limit.shared = Some(Shared {
+    store: store.clone(),
+    prefix: "gway:rl:test".into(),
+  });
+  limit
+}
+
+#[tokio::test]
+async fn test_redis_limit() -> anyhow::Result<()> {
+  let Some((server, port)) = redis_server().await? else {
+    return Ok(());
+  };
+  let store = Arc::new(RedisStore::new(&format!("redis://127.0.0.1:{port}"))?);
+  // 模拟两个实例, 共用 Redis 中的计数
+  let a = limit(&store);
+  let b = limit(&store);
+  assert_eq!(a.acquire("1.1.1.1".into()).await, Ok(()));
+  assert_eq!(b.acquire("1.1.1.1".into()).await, Ok(()));
+  assert_eq!(a.acquire("1.1.1.1".into()).await, Ok(()));
+  assert_eq!(b.acquire("1.1.1.1".into()).await, Err(1));
+  assert_eq!(a.acquire("1.1.1.2".into()).await, Ok(()));
+
+  // Redis 不可用时回退到本地计数
+  drop(server);
+  tokio::time::sleep(Duration::from_millis(200)).await;
+  assert_eq!(b.acquire("1.1.1.1".into()).await, Ok(()));
+  assert!(store.is_down());
+  for _ in 0..2 {
+    assert_eq!(b.acquire("1.1.1.1".into()).await, Ok(()));
+  }
+  assert_eq!(b.acquire("1.1.1.1".into()).await, Err(1));
+  Ok(())
+}
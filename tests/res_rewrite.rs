@@ -0,0 +1,68 @@
+mod comm;
+
+use std::sync::Arc;
+
+use gway::{ResRewrite, Vars};
+use http_body_util::Full;
+use hyper::{Request, body::Bytes};
+
+#[test]
+fn test_rewrite_url() -> anyhow::Result<()> {
+  let rewrite = ResRewrite {
+    url_li: [("http://127.0.0.1:9080/".into(), "https://{host}/".parse()?)].into(),
+    ..Default::default()
+  };
+  let vars = Vars {
+    host: "a.test",
+    ..Default::default()
+  };
+  assert_eq!(
+    rewrite.url("HTTP://127.0.0.1:9080/x?y=1", &vars).as_deref(),
+    Some("https://a.test/x?y=1")
+  );
+  assert_eq!(rewrite.url("https://b.test/x", &vars), None);
+  Ok(())
+}
+
+#[tokio::test]
+async fn test_res_rewrite() -> anyhow::Result<()> {
+  let addr = comm::upstream().await?;
+  let route = comm::route(addr);
+  let rewrite = ResRewrite {
+    url_li: [("http://127.0.0.1:9080/".into(), "https://{host}/".parse()?)].into(),
+    cookie_domain_li: [
+      ("127.0.0.1".into(), "{host}".parse()?),
+      ("internal".into(), "".parse()?),
+    ]
+    .into(),
+    cookie_path_li: [("/app/".into(), "/".into())].into(),
+    cookie_secure: true,
+  };
+  route.with_site("a.test", |conf| conf.res_rewrite = Some(Arc::new(rewrite)));
+  let route = Arc::new(route);
+
+  let req = Request::builder()
+    .uri("/")
+    .header("host", "a.test")
+    .header("x-set-location", "http://127.0.0.1:9080/login?next=/")
+    .header("x-set-content-location", "http://other.test/x")
+    .header("x-set-refresh", "5; url=http://127.0.0.1:9080/r")
+    .header("x-set-set-cookie", "a=1; Domain=.127.0.0.1; Path=/app/x")
+    .header("x-set-set-cookie", "b=2; domain=internal; Secure")
+    .body(Full::new(Bytes::new()))?;
+  let res = gway::proxy(req, route.clone()).await;
+  let headers = res.headers();
+  assert_eq!(headers["location"], "https://a.test/login?next=/");
+  assert_eq!(headers["content-location"], "http://other.test/x");
+  assert_eq!(headers["refresh"], "5; url=https://a.test/r");
+  let cookie_li: Vec<_> = headers
+    .get_all("set-cookie")
+    .iter()
+    .map(|v| v.to_str())
+    .collect::<Result<_, _>>()?;
+  assert_eq!(
+    cookie_li,
+    ["a=1; Domain=a.test; Path=/x; Secure", "b=2; Secure"]
+  );
+  Ok(())
+}
@@ -0,0 +1,105 @@
+mod comm;
+
+use std::{sync::Arc, time::Duration};
+
+use gway::{Park, Retry, Route, Upstream};
+use http_body_util::Full;
+use hyper::{Method, Request, StatusCode, body::Bytes};
+
+async fn route(retry: Retry) -> anyhow::Result<Arc<Route>> {
+  let addr = comm::upstream().await?;
+  let route = comm::route_to(Upstream {
+    max_retry: 2,
+    retry,
+    ..comm::up(addr)
+  });
+  Ok(Arc::new(route))
+}
+
+// 返回状态码和上游收到的请求数
+async fn send(
+  route: &Arc<Route>,
+  method: Method,
+  status: &str,
+) -> anyhow::Result<(StatusCode, u64)> {
+  let req = Request::builder()
+    .method(method)
+    .uri("/")
+    .header("host", "a.test")
+    .header("x-status", status)
+    .body(Full::new(Bytes::new()))?;
+  let res = gway::proxy(req, route.clone()).await;
+  Ok((res.status(), res.headers()["x-n"].to_str()?.parse()?))
+}
+
+#[tokio::test]
+async fn test_retry_status() -> anyhow::Result<()> {
+  let route = route(Retry::default()).await?;
+  // 幂等请求遇到 503 换地址重试, 直到用完重试次数
+  assert_eq!(
+    send(&route, Method::GET, "503").await?,
+    (StatusCode::SERVICE_UNAVAILABLE, 3)
+  );
+  // 非幂等请求不重试
+  assert_eq!(
+    send(&route, Method::POST, "503").await?,
+    (StatusCode::SERVICE_UNAVAILABLE, 4)
+  );
+  // 不在重试列表中的状态码
+  assert_eq!(
+    send(&route, Method::GET, "500").await?,
+    (StatusCode::INTERNAL_SERVER_ERROR, 5)
+  );
+  assert_eq!(send(&route, Method::GET, "200").await?, (StatusCode::OK, 6));
+  Ok(())
+}
+
+#[tokio::test]
+async fn test_retry_budget() -> anyhow::Result<()> {
+  let mut retry = Retry::default();
+  retry.budget_min = 1;
+  retry.budget_ratio = 0.0;
+  let route = route(retry).await?;
+  assert_eq!(send(&route, Method::GET, "503").await?.1, 2);
+  // 预算用完后不再重试
+  assert_eq!(send(&route, Method::GET, "503").await?.1, 3);
+  Ok(())
+}
+
+#[tokio::test]
+async fn test_retry_budget_park() -> anyhow::Result<()> {
+  // 整组不可用时等待恢复, 不受重试预算限制
+  let addr = tokio::net::TcpListener::bind("127.0.0.1:0")
+    .await?
+    .local_addr()?;
+  let mut retry = Retry::default();
+  retry.budget_min = 0;
+  retry.budget_ratio = 0.0;
+  let route = Arc::new(comm::route_to(Upstream {
+    max_retry: 2,
+    park: Some(Arc::new(Park::new(Duration::from_secs(3)))),
+    retry,
+    ..comm::up(addr)
+  }));
+  let req = Request::builder()
+    .uri("/")
+    .header("host", "a.test")
+    .body(Full::new(Bytes::new()))?;
+  let task = tokio::spawn(gway::proxy(req, route));
+  tokio::time::sleep(Duration::from_millis(300)).await;
+  comm::upstream_on(addr).await?;
+  assert_eq!(task.await?.status(), StatusCode::OK);
+  Ok(())
+}
+
+#[test]
+fn test_retry_policy() {
+  let retry = Retry::default();
+  // 连接失败时请求未发出, 都可以重试
+  assert!(retry.on_error(&Method::POST, true));
+  assert!(!retry.on_error(&Method::POST, false));
+  assert!(retry.on_error(&Method::PUT, false));
+  for n in 1..20 {
+    assert!(retry.backoff(n) <= Duration::from_secs(1));
+  }
+}
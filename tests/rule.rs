@@ -0,0 +1,60 @@
+mod comm;
+
+use std::sync::Arc;
+
+use gway::{PathMatch, Rule};
+use http_body_util::{BodyExt, Full};
+use hyper::{Method, Request, body::Bytes};
+use regex::Regex;
+
+#[tokio::test]
+async fn test_rule() -> anyhow::Result<()> {
+  let web = comm::upstream().await?;
+  let api = comm::upstream().await?;
+  let mut route = comm::route(web);
+  route.add_upstream("api", comm::up(api));
+  route.set("rule.test", "rule.test", "up");
+
+  let api_up = route.upstream("api").ok_or(anyhow::anyhow!("no api"))?;
+  let mut strip = Rule::new(PathMatch::Prefix("/api/".into()), api_up.clone());
+  strip.strip = Some("/api".into());
+  strip.add = Some("/v2/".into());
+  let mut post = Rule::new(PathMatch::Exact("/form".into()), api_up.clone());
+  post.method_li = [Method::POST].into();
+  let mut header = Rule::new(PathMatch::Regex(Regex::new(r"^/u/\d+$")?), api_up);
+  header.header_li = ["x-beta".parse()?].into();
+  route.with_site("rule.test", |conf| {
+    conf.rule_li = [strip, post, header].into();
+  });
+  let route = Arc::new(route);
+
+  let send = async |method: Method, path: &str, beta: bool| -> anyhow::Result<(String, String)> {
+    let mut req = Request::builder()
+      .method(method)
+      .uri(path)
+      .header("host", "rule.test");
+    if beta {
+      req = req.header("x-beta", "1");
+    }
+    let res = gway::proxy(req.body(Full::new(Bytes::new()))?, route.clone()).await;
+    let upstream = res.headers()["x-upstream"].to_str()?.to_owned();
+    let body = res.into_body().collect().await?.to_bytes();
+    Ok((upstream, String::from_utf8(body.to_vec())?))
+  };
+
+  let (web, api) = (web.to_string(), api.to_string());
+  assert_eq!(
+    send(Method::GET, "/api/users?id=1", false).await?,
+    (api.clone(), "rule.test/v2/users?id=1".into())
+  );
+  assert_eq!(
+    send(Method::GET, "/apix", false).await?,
+    (web.clone(), "rule.test/apix".into())
+  );
+  assert_eq!(send(Method::POST, "/form", false).await?.0, api);
+  assert_eq!(send(Method::GET, "/form", false).await?.0, web);
+  assert_eq!(send(Method::GET, "/u/12", true).await?.0, api);
+  assert_eq!(send(Method::GET, "/u/12", false).await?.0, web);
+  assert_eq!(send(Method::GET, "/u/ab", true).await?.0, web);
+  Ok(())
+}
@@ -0,0 +1,65 @@
+mod comm;
+
+use std::sync::Arc;
+
+use gway::{Peer, SecHeader};
+use http_body_util::Full;
+use hyper::{HeaderMap, Request, StatusCode, body::Bytes, header::HeaderValue};
+
+#[tokio::test]
+async fn test_sec_header() -> anyhow::Result<()> {
+  let addr = comm::upstream().await?;
+  let mut route = comm::route(addr);
+  route.add_upstream("down", comm::up("127.0.0.1:1".parse()?));
+  route.set("down.test", "down.test", "down");
+  let sec_header = Arc::new(SecHeader {
+    csp: Some(HeaderValue::from_static("default-src 'self'")),
+    ..Default::default()
+  });
+  for host in ["a.test", "down.test"] {
+    route.with_site(host, |conf| conf.sec_header = Some(sec_header.clone()));
+  }
+  let route = Arc::new(route);
+
+  let get = async |host: &str, tls: bool| -> anyhow::Result<(StatusCode, HeaderMap)> {
+    let mut req = Request::builder()
+      .uri("/")
+      .header("host", host)
+      .header("x-set-x-frame-options", "DENY")
+      .body(Full::new(Bytes::new()))?;
+    req
+      .extensions_mut()
+      .insert(Peer::new("10.0.0.1:1234".parse()?, tls));
+    let res = gway::proxy(req, route.clone()).await;
+    Ok((res.status(), res.headers().clone()))
+  };
+
+  let (_, headers) = get("a.test", true).await?;
+  assert_eq!(
+    headers["strict-transport-security"],
+    "max-age=63072000; includeSubDomains; preload"
+  );
+  assert_eq!(headers["content-security-policy"], "default-src 'self'");
+  assert_eq!(headers["x-content-type-options"], "nosniff");
+  assert_eq!(
+    headers["referrer-policy"],
+    "strict-origin-when-cross-origin"
+  );
+  // 上游已设置的值不覆盖
+  assert_eq!(headers["x-frame-options"], "DENY");
+
+  let (_, headers) = get("a.test", false).await?;
+  assert!(!headers.contains_key("strict-transport-security"));
+
+  // 网关生成的错误页也带上
+  let (status, headers) = get("down.test", true).await?;
+  assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+  assert_eq!(headers["x-frame-options"], "SAMEORIGIN");
+  assert!(headers.contains_key("strict-transport-security"));
+
+  // 跳转到规范域名时按目标站点的配置
+  let (status, headers) = get("x.a.test", true).await?;
+  assert_eq!(status, StatusCode::MOVED_PERMANENTLY);
+  assert_eq!(headers["content-security-policy"], "default-src 'self'");
+  Ok(())
+}
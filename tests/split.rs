@@ -0,0 +1,70 @@
+mod comm;
+
+use std::sync::Arc;
+
+use gway::{Group, Split, Upstream};
+use hyper::HeaderMap;
+
+fn upstream() -> Arc<Upstream> {
+  Arc::new(comm::up("127.0.0.1:1".parse().unwrap()))
+}
+
+fn headers(li: &[(&'static str, &str)]) -> anyhow::Result<HeaderMap> {
+  let mut map = HeaderMap::new();
+  for (k, v) in li {
+    map.insert(*k, v.parse()?);
+  }
+  Ok(map)
+}
+
+#[test]
+fn test_split() -> anyhow::Result<()> {
+  let mut split = Split::new([
+    Group::new("stable", upstream(), 95),
+    Group::new("canary", upstream(), 5),
+  ]);
+  split.pin_header = Some("x-canary".parse()?);
+  split.key_header = Some("x-user".parse()?);
+  split.cookie = Some("gway_group".into());
+
+  let name = |li: &[(&'static str, &str)]| -> anyhow::Result<String> {
+    let pick = split
+      .pick(&headers(li)?)
+      .ok_or(anyhow::anyhow!("no group"))?;
+    Ok(pick.group.name.to_string())
+  };
+
+  // 按权重随机分配并写 cookie
+  let mut canary = 0;
+  for _ in 0..10000 {
+    let pick = split
+      .pick(&HeaderMap::new())
+      .ok_or(anyhow::anyhow!("no group"))?;
+    let cookie = pick.set_cookie.ok_or(anyhow::anyhow!("no cookie"))?;
+    assert!(cookie.starts_with(&format!("gway_group={}", pick.group.name)));
+    if pick.group.name == "canary" {
+      canary += 1;
+    }
+  }
+  assert!((300..700).contains(&canary), "{canary}");
+
+  // cookie 和请求头固定分组
+  assert_eq!(name(&[("cookie", "gway_group=canary")])?, "canary");
+  assert_eq!(name(&[("x-canary", "canary")])?, "canary");
+
+  // 同一个用户总是分到同一组
+  let user = name(&[("x-user", "u1")])?;
+  for _ in 0..10 {
+    assert_eq!(name(&[("x-user", "u1")])?, user);
+  }
+
+  // 权重降为 0 后 cookie 不再生效, 但请求头仍可固定
+  assert!(split.set_weight("canary", 0));
+  assert!(!split.set_weight("none", 1));
+  assert_eq!(name(&[("cookie", "gway_group=canary")])?, "stable");
+  assert_eq!(name(&[("x-canary", "canary")])?, "canary");
+
+  split.set_weight("stable", 0);
+  assert!(split.pick(&HeaderMap::new()).is_none());
+  Ok(())
+}
@@ -0,0 +1,67 @@
+mod comm;
+
+use std::{net::SocketAddr, sync::Arc};
+
+use gway::{Protocol, Sticky, Upstream};
+use http_body_util::Full;
+use hyper::{Request, body::Bytes};
+
+#[tokio::test]
+async fn test_sticky() -> anyhow::Result<()> {
+  let mut addr_li = Vec::new();
+  for _ in 0..3 {
+    addr_li.push(comm::upstream().await?);
+  }
+  // 已关闭的端口
+  let dead: SocketAddr = {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    listener.local_addr()?
+  };
+  addr_li.push(dead);
+
+  let sticky = Sticky::new("gway_sticky", "secret");
+  let mut route = comm::route_to(Upstream {
+    addr_li: addr_li.into(),
+    protocol: Protocol::H1,
+    max_retry: 3,
+    sticky: Some(sticky.clone()),
+    ..Default::default()
+  });
+  route.set("sticky.test", "sticky.test", "up");
+  let route = Arc::new(route);
+
+  let get = async |cookie: Option<&str>| -> anyhow::Result<(String, Option<String>)> {
+    let mut req = Request::builder().uri("/").header("host", "sticky.test");
+    if let Some(cookie) = cookie {
+      req = req.header("cookie", cookie);
+    }
+    let res = gway::proxy(req.body(Full::new(Bytes::new()))?, route.clone()).await;
+    let set_cookie = res
+      .headers()
+      .get("set-cookie")
+      .and_then(|v| v.to_str().ok())
+      .map(|v| v.split(';').next().unwrap_or_default().to_owned());
+    Ok((res.headers()["x-upstream"].to_str()?.to_owned(), set_cookie))
+  };
+
+  let (first, cookie) = get(None).await?;
+  let cookie = cookie.ok_or(anyhow::anyhow!("no set-cookie"))?;
+  assert!(!cookie.contains(&first), "cookie 不应暴露地址");
+  for _ in 0..10 {
+    assert_eq!(get(Some(&cookie)).await?, (first.clone(), None));
+  }
+
+  // 签名错误时重新分配
+  let (_, set_cookie) = get(Some("gway_sticky=00112233445566778899aabbccddeeff")).await?;
+  assert!(set_cookie.is_some());
+
+  // 后端不可用时回退到正常负载均衡, 并写新的 cookie
+  let cookie = format!("gway_sticky={}", sticky.sign(dead));
+  let (addr, set_cookie) = get(Some(&cookie)).await?;
+  assert_ne!(addr, dead.to_string());
+  assert_eq!(
+    set_cookie,
+    Some(format!("gway_sticky={}", sticky.sign(addr.parse()?)))
+  );
+  Ok(())
+}
@@ -0,0 +1,39 @@
+mod comm;
+
+use std::sync::Arc;
+
+use gway::{UpHost, Upstream};
+use http_body_util::{BodyExt, Full};
+use hyper::{Request, Version, body::Bytes};
+
+#[tokio::test]
+async fn test_up_host() -> anyhow::Result<()> {
+  let addr = comm::upstream().await?;
+  let mut route = comm::route(addr);
+  route.add_upstream(
+    "set",
+    Upstream {
+      host: UpHost::Set("backend.local".into()),
+      ..comm::up(addr)
+    },
+  );
+  route.set("keep.test", "keep.test", "up");
+  route.set("set.test", "set.test", "set");
+  let route = Arc::new(route);
+
+  // 模拟 h2 请求: 绝对 uri, 没有 Host 头
+  let get = async |uri: &str| -> anyhow::Result<String> {
+    let req = Request::builder()
+      .uri(uri)
+      .version(Version::HTTP_2)
+      .body(Full::new(Bytes::new()))?;
+    let res = gway::proxy(req, route.clone()).await;
+    Ok(String::from_utf8(
+      res.into_body().collect().await?.to_bytes().to_vec(),
+    )?)
+  };
+
+  assert_eq!(get("https://keep.test/p?q=1").await?, "keep.test/p?q=1");
+  assert_eq!(get("https://set.test/p?q=1").await?, "backend.local/p?q=1");
+  Ok(())
+}
@@ -0,0 +1,92 @@
+mod comm;
+
+use std::sync::Arc;
+
+use gway::{HeaderOp, HeaderRule, Tpl, Vars};
+use http_body_util::{BodyExt, Full};
+use hyper::{Request, StatusCode, body::Bytes, header::HeaderName};
+
+#[test]
+fn test_tpl() -> anyhow::Result<()> {
+  let tpl: Tpl = "/t/{1}/{0}{path}?{query}&h={host}".parse()?;
+  let capture = ["a".into(), "b".into()];
+  let vars = Vars {
+    capture: &capture,
+    host: "a.b.test",
+    path_and_query: "/x?y=1",
+    ..Default::default()
+  };
+  assert_eq!(tpl.render(&vars), "/t/b/a/x?y=1&h=a.b.test");
+  assert!("/{x}".parse::<Tpl>().is_err());
+  assert!("/{0".parse::<Tpl>().is_err());
+  Ok(())
+}
+
+#[tokio::test]
+async fn test_wildcard() -> anyhow::Result<()> {
+  let addr = comm::upstream().await?;
+  let mut route = comm::route(addr);
+  route.set("*.one.test", "one.test", "up");
+  route.set("**.many.test", "many.test", "up");
+  route.set("*.a.many.test", "many.test", "up");
+  route.set("exact.one.test", "one.test", "up");
+  route.with_site("*.one.test", |conf| {
+    conf.path_tpl = "/tenant/{0}{uri}".parse().ok();
+    conf.header_rule_li = [HeaderRule::req(
+      HeaderName::from_static("x-tenant"),
+      HeaderOp::Set("{0}".parse().unwrap()),
+    )]
+    .into();
+  });
+  route.with_site("**.many.test", |conf| {
+    conf.path_tpl = "/{1}/{0}{path}".parse().ok();
+  });
+  let route = Arc::new(route);
+
+  let get = async |host: &str| -> anyhow::Result<(StatusCode, String, Option<String>)> {
+    let req = Request::builder()
+      .uri("/p?q=1")
+      .header("host", host)
+      .body(Full::new(Bytes::new()))?;
+    let res = gway::proxy(req, route.clone()).await;
+    let status = res.status();
+    let tenant = res
+      .headers()
+      .get("x-req-x-tenant")
+      .and_then(|v| v.to_str().ok())
+      .map(Into::into);
+    let body = res.into_body().collect().await?.to_bytes();
+    Ok((status, String::from_utf8(body.to_vec())?, tenant))
+  };
+
+  // 单级泛域名, 捕获的标签用于改写路径和请求头
+  assert_eq!(
+    get("acme.one.test").await?,
+    (
+      StatusCode::OK,
+      "acme.one.test/tenant/acme/p?q=1".into(),
+      Some("acme".into())
+    )
+  );
+  // 精确匹配优先
+  assert_eq!(
+    get("exact.one.test").await?,
+    (StatusCode::OK, "exact.one.test/p?q=1".into(), None)
+  );
+  // 单级泛域名不匹配多级, 回退到父域名跳转
+  assert_eq!(
+    get("x.acme.one.test").await?.0,
+    StatusCode::MOVED_PERMANENTLY
+  );
+  // 多级泛域名
+  assert_eq!(
+    get("x.y.many.test").await?,
+    (StatusCode::OK, "x.y.many.test/y/x/p".into(), None)
+  );
+  // 更长的后缀优先
+  assert_eq!(
+    get("z.a.many.test").await?,
+    (StatusCode::OK, "z.a.many.test/p?q=1".into(), None)
+  );
+  Ok(())
+}